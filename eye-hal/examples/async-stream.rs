@@ -0,0 +1,39 @@
+use futures::executor::block_on;
+use futures::StreamExt;
+
+use eye_hal::traits::{Context, Device};
+use eye_hal::{PlatformContext, Result};
+
+fn main() -> Result<()> {
+    // Create a context
+    let ctx = PlatformContext::all().next().expect("no platform context");
+
+    // Query for available devices.
+    let devices = ctx.devices()?;
+
+    // First, we need a capture device to read images from. For this example, let's just choose
+    // whatever device is first in the list.
+    let dev = ctx.open_device(&devices[0].uri)?;
+
+    // Query for available streams and just choose the first one.
+    let streams = dev.streams()?;
+    let stream_desc = streams[0].clone();
+    println!("Stream: {:?}", stream_desc);
+
+    // Turn the native image stream into an asynchronous one. Waiting for frames does not block
+    // the executor, so this works just the same inside of e.g. a tokio runtime.
+    let mut stream = dev.start_stream(&stream_desc)?.into_async()?;
+
+    block_on(async {
+        while let Some(frame) = stream.next().await {
+            let frame = frame?;
+            println!(
+                "Frame: sequence {}, {} bytes",
+                frame.meta.sequence,
+                frame.len()
+            );
+        }
+
+        Ok(())
+    })
+}
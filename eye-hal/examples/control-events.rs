@@ -0,0 +1,35 @@
+use eye_hal::traits::{Context, Device};
+use eye_hal::{PlatformContext, Result};
+
+fn main() -> Result<()> {
+    // Create a context
+    let ctx = PlatformContext::default();
+
+    // Open the first device
+    let list = ctx.devices()?;
+    let dev = ctx.open_device(&list[0].uri)?;
+
+    // Map control IDs to names for printing.
+    let controls = dev.controls()?;
+    let name = |id: u32| {
+        controls
+            .iter()
+            .find(|ctrl| ctrl.id == id)
+            .map_or("<unknown>", |ctrl| ctrl.name.as_str())
+    };
+
+    // Watch all controls. Iterating the subscription blocks until something changes, e.g. when
+    // another application adjusts a control.
+    for event in dev.subscribe_controls(&[])? {
+        let event = event?;
+        println!(
+            "{}: {:?} (value: {:?}, flags: {:?})",
+            name(event.id),
+            event.changes,
+            event.value,
+            event.flags
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,18 @@
+use eye_hal::device::Event;
+use eye_hal::{PlatformContext, Result};
+
+fn main() -> Result<()> {
+    // Create a context
+    let ctx = PlatformContext::default();
+
+    // Watch for devices being connected and disconnected. Iterating the monitor blocks until
+    // something happens.
+    for event in ctx.monitor()? {
+        match event? {
+            Event::Added(desc) => println!("+ {} ({})", desc.uri, desc.product),
+            Event::Removed(desc) => println!("- {} ({})", desc.uri, desc.product),
+        }
+    }
+
+    Ok(())
+}
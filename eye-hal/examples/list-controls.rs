@@ -1,4 +1,4 @@
-use eye_hal::control::{MenuItem, Type};
+use eye_hal::control::{MenuValue, Type};
 use eye_hal::traits::{Context, Device};
 use eye_hal::{PlatformContext, Result};
 
@@ -30,6 +30,9 @@ fn main() -> Result<()> {
                     println!("      Type    : Number");
                     println!("      Range   : ({}, {})", range.0, range.1);
                     println!("      Step    : {}", step);
+                    if let Some(scale) = &ctrl.scale {
+                        println!("      Unit    : {:?} (x{})", scale.unit, scale.factor);
+                    }
                 }
                 Type::String => {
                     println!("      Type    : String");
@@ -40,12 +43,12 @@ fn main() -> Result<()> {
                 Type::Menu(items) => {
                     println!("      Type    : Menu ==>");
                     for item in items {
-                        match item {
-                            MenuItem::String(str) => {
-                                println!("       - {}", str);
+                        match &item.value {
+                            MenuValue::String(str) => {
+                                println!("       - {}: {}", item.index, str);
                             }
-                            MenuItem::Number(val) => {
-                                println!("       - {}", val);
+                            MenuValue::Number(val) => {
+                                println!("       - {}: {}", item.index, val);
                             }
                         }
                     }
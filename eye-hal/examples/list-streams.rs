@@ -33,7 +33,7 @@ fn main() -> Result<()> {
                 print!("      {}x{}", res.0, res.1);
                 print!(" : [");
                 for stream in streams {
-                    print!("{:?}, ", stream.interval);
+                    print!("{}, ", stream.interval);
                 }
                 println!("]");
             }
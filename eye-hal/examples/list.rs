@@ -11,6 +11,9 @@ fn main() -> Result<()> {
         for desc in list {
             println!("{}", desc.uri);
             println!("  product : {}", desc.product);
+            if !desc.io.is_empty() {
+                println!("  io      : {:?}", desc.io);
+            }
         }
     }
 
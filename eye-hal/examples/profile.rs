@@ -0,0 +1,35 @@
+use std::{env, fs};
+
+use eye_hal::profile::Profile;
+use eye_hal::traits::Context;
+use eye_hal::{PlatformContext, Result};
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 || (args[1] != "save" && args[1] != "load") {
+        println!("usage: {} <save|load> <file>", args[0]);
+        return Ok(());
+    }
+
+    // Create a context
+    let ctx = PlatformContext::default();
+
+    // Open the first device
+    let list = ctx.devices()?;
+    let mut dev = ctx.open_device(&list[0].uri)?;
+
+    if args[1] == "save" {
+        // Take a snapshot of the current settings.
+        let profile = Profile::capture(&dev)?;
+        fs::write(&args[2], profile.to_toml()?)?;
+        println!("Saved {} controls", profile.entries.len());
+    } else {
+        // Restore the settings, controls which cannot be set are reported individually.
+        let profile = Profile::from_toml(&fs::read_to_string(&args[2])?)?;
+        for failure in profile.apply(&mut dev) {
+            println!("Failed to set {}: {}", failure.name, failure.error);
+        }
+    }
+
+    Ok(())
+}
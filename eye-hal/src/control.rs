@@ -1,5 +1,12 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::thread;
+use std::time::{Duration, Instant};
+
 use bitflags::bitflags;
 
+use crate::error::{Error, ErrorKind, Result};
+
 #[derive(Debug, Clone)]
 /// Device control
 pub struct Descriptor {
@@ -11,6 +18,14 @@ pub struct Descriptor {
     pub typ: Type,
     /// State flags
     pub flags: Flags,
+    /// Well-known meaning of the control, if any
+    pub kind: Option<Kind>,
+    /// Physical unit of numerical values, if known
+    pub scale: Option<Scale>,
+    /// Default value, if known
+    pub default: Option<State>,
+    /// Value at the time the control was queried, if it could be read
+    pub value: Option<State>,
 }
 
 impl Descriptor {
@@ -23,9 +38,224 @@ impl Descriptor {
     pub fn writable(&self) -> bool {
         self.flags & Flags::WRITE == Flags::WRITE
     }
+
+    /// Returns true if the control does not apply to the current configuration
+    pub fn inactive(&self) -> bool {
+        self.flags & Flags::INACTIVE == Flags::INACTIVE
+    }
+
+    /// Checks whether a value can be written to the control
+    ///
+    /// Fails with [`ErrorKind::InvalidArgument`] if the control is not writable, the state type
+    /// does not match or the value is out of range or off-step. Fails with
+    /// [`ErrorKind::NotSupported`] if the control is inactive, e.g. a manual exposure time while
+    /// automatic exposure is on.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - Value to check
+    pub fn validate(&self, state: &State) -> Result<()> {
+        if !self.writable() {
+            return Err(Error::new(
+                ErrorKind::InvalidArgument,
+                format!("control '{}' is not writable", self.name),
+            ));
+        }
+        if self.inactive() {
+            return Err(Error::new(
+                ErrorKind::NotSupported,
+                format!(
+                    "control '{}' is inactive in the current configuration",
+                    self.name
+                ),
+            ));
+        }
+
+        match (&self.typ, state) {
+            (Type::Stateless, State::None)
+            | (Type::Boolean, State::Boolean(_))
+            | (Type::String, State::String(_))
+            | (Type::Bitmask, State::Bitmask(_)) => Ok(()),
+            (Type::Number { range, step }, State::Number(_) | State::Integer(_)) => {
+                let value = number(state);
+                if value < range.0 || value > range.1 {
+                    return Err(Error::new(
+                        ErrorKind::InvalidArgument,
+                        format!(
+                            "value {} of control '{}' is out of range ({}, {})",
+                            value, self.name, range.0, range.1
+                        ),
+                    ));
+                }
+
+                if *step > 0.0 {
+                    let steps = (value - range.0) / *step as f64;
+                    if (steps - steps.round()).abs() > 1e-6 {
+                        return Err(Error::new(
+                            ErrorKind::InvalidArgument,
+                            format!(
+                                "value {} of control '{}' is not a multiple of step {}",
+                                value, self.name, step
+                            ),
+                        ));
+                    }
+                }
+
+                Ok(())
+            }
+            (Type::Menu(items), State::Menu(_) | State::Number(_) | State::Integer(_)) => {
+                let index = match state {
+                    State::Menu(index) => *index as f64,
+                    _ => number(state),
+                };
+                if items.iter().any(|item| item.index as f64 == index) {
+                    Ok(())
+                } else {
+                    Err(Error::new(
+                        ErrorKind::InvalidArgument,
+                        format!("control '{}' has no menu item {}", self.name, index),
+                    ))
+                }
+            }
+            _ => Err(Error::new(
+                ErrorKind::InvalidArgument,
+                format!("state {:?} does not match control '{}'", state, self.name),
+            )),
+        }
+    }
+
+    /// Clamps a value into the range of the control and snaps it to the closest step
+    ///
+    /// Values which cannot be adjusted are validated like in [`Descriptor::validate`].
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - Value to adjust
+    pub fn clamp(&self, state: &State) -> Result<State> {
+        let (range, step) = match (&self.typ, state) {
+            (Type::Number { range, step }, State::Number(_) | State::Integer(_)) => (range, step),
+            _ => {
+                self.validate(state)?;
+                return Ok(state.clone());
+            }
+        };
+
+        let mut value = number(state).max(range.0).min(range.1);
+        if *step > 0.0 {
+            let step = *step as f64;
+            value = range.0 + ((value - range.0) / step).round() * step;
+            // rounding up may have pushed the value past the maximum
+            if value > range.1 {
+                value -= step;
+            }
+        }
+
+        let state = match state {
+            State::Integer(_) => State::Integer(value.round() as i64),
+            _ => State::Number(value),
+        };
+        self.validate(&state)?;
+        Ok(state)
+    }
 }
 
-#[derive(Debug, Clone)]
+fn number(state: &State) -> f64 {
+    match state {
+        State::Number(value) => *value,
+        State::Integer(value) => *value as f64,
+        _ => f64::NAN,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Well-known control
+///
+/// Control identifiers are backend specific, so this is used to find controls with the same
+/// meaning across backends. Note that the state type of a control may still differ, e.g. auto
+/// exposure is a menu on V4L2 and a switch elsewhere.
+pub enum Kind {
+    /// Picture brightness
+    Brightness,
+    /// Picture contrast
+    Contrast,
+    /// Color saturation
+    Saturation,
+    /// Color hue
+    Hue,
+    /// Gamma adjustment
+    Gamma,
+    /// Picture sharpness
+    Sharpness,
+    /// Backlight compensation
+    BacklightCompensation,
+    /// Power line frequency filter, used to avoid flicker
+    PowerLineFrequency,
+    /// Gain, analogue or digital depending on the device
+    Gain,
+    /// Automatic gain control
+    AutoGain,
+    /// White balance temperature
+    WhiteBalance,
+    /// Automatic white balance
+    AutoWhiteBalance,
+    /// Exposure time
+    Exposure,
+    /// Automatic exposure
+    AutoExposure,
+    /// Focus position
+    Focus,
+    /// Automatic focus
+    AutoFocus,
+    /// Zoom position
+    Zoom,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Physical unit
+pub enum Unit {
+    /// Time in microseconds
+    Microseconds,
+    /// Ratio in decibels (not mapped by any backend yet)
+    Decibels,
+    /// Color temperature in Kelvin
+    Kelvin,
+    /// Optical power in dioptres (not mapped by any backend yet)
+    Dioptres,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Relation between numerical control values and a physical unit
+pub struct Scale {
+    /// Physical unit
+    pub unit: Unit,
+    /// Amount of the physical unit represented by a control value of one
+    pub factor: f64,
+}
+
+impl Scale {
+    /// Returns a new scale
+    ///
+    /// # Arguments
+    ///
+    /// * `unit` - Physical unit
+    /// * `factor` - Amount of the unit represented by a control value of one
+    pub fn new(unit: Unit, factor: f64) -> Self {
+        Scale { unit, factor }
+    }
+
+    /// Converts a control value into the physical unit
+    pub fn to_unit(&self, value: f64) -> f64 {
+        value * self.factor
+    }
+
+    /// Converts a value in the physical unit into a control value
+    pub fn from_unit(&self, value: f64) -> f64 {
+        value / self.factor
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 /// Device control type
 pub enum Type {
     /// Stateless controls
@@ -47,9 +277,18 @@ pub enum Type {
     Menu(Vec<MenuItem>),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 /// Device control menu item
-pub enum MenuItem {
+pub struct MenuItem {
+    /// Index used to select the item, menus may be sparse
+    pub index: u32,
+    /// Item value
+    pub value: MenuValue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Device control menu item value
+pub enum MenuValue {
     /// String value
     String(String),
     /// Numerical value
@@ -66,10 +305,14 @@ bitflags! {
         const READ                  = 0x001;
         /// Value can be written
         const WRITE                 = 0x002;
+        /// Control does not apply to the current configuration, e.g. because an automatic mode
+        /// is on
+        const INACTIVE              = 0x004;
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Device control state
 pub enum State {
     /* Stateless controls */
@@ -79,4 +322,325 @@ pub enum State {
     String(String),
     Boolean(bool),
     Number(f64),
+    Integer(i64),
+    Bitmask(u32),
+
+    /* Menu controls, selecting an item by its index */
+    Menu(u32),
+}
+
+bitflags! {
+    /// Control event changes
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub struct Changes: u32 {
+        /// The value changed
+        const VALUE                 = 0x001;
+        /// The flags changed
+        const FLAGS                 = 0x002;
+        /// The range or step size changed
+        const RANGE                 = 0x004;
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Control change event
+pub struct Event {
+    /// Control identifier
+    pub id: u32,
+    /// Properties which changed
+    pub changes: Changes,
+    /// Current value, if available
+    pub value: Option<State>,
+    /// Current flags
+    pub flags: Flags,
+    /// Current valid value range (inclusive on both ends) of numerical controls
+    pub range: Option<(f64, f64)>,
+    /// Current range step size of numerical controls
+    pub step: Option<f32>,
+}
+
+impl Event {
+    fn new(ctrl: &Descriptor, changes: Changes) -> Self {
+        let (range, step) = match ctrl.typ {
+            Type::Number { range, step } => (Some(range), Some(step)),
+            _ => (None, None),
+        };
+
+        Event {
+            id: ctrl.id,
+            changes,
+            value: ctrl.value.clone(),
+            flags: ctrl.flags,
+            range,
+            step,
+        }
+    }
+}
+
+/// Control event emulation
+///
+/// For backends which do not publish control events, the controls are read periodically and
+/// compared against the previous snapshot.
+pub struct Poller<'a> {
+    read: Box<dyn FnMut() -> Result<Vec<Descriptor>> + 'a>,
+    ids: Vec<u32>,
+    interval: Duration,
+    polled: Instant,
+    known: HashMap<u32, Descriptor>,
+    pending: VecDeque<Event>,
+}
+
+impl<'a> Poller<'a> {
+    /// Creates a new poller and takes the initial snapshot
+    ///
+    /// # Arguments
+    ///
+    /// * `read` - Function which returns the controls including their current values
+    /// * `ids` - Control identifiers to watch, all controls are watched if empty
+    pub fn new<F>(read: F, ids: &[u32]) -> Result<Self>
+    where
+        F: FnMut() -> Result<Vec<Descriptor>> + 'a,
+    {
+        let mut poller = Poller {
+            read: Box::new(read),
+            ids: ids.to_vec(),
+            interval: Duration::from_millis(100),
+            polled: Instant::now(),
+            known: HashMap::new(),
+            pending: VecDeque::new(),
+        };
+
+        poller.poll()?;
+        Ok(poller)
+    }
+
+    /// Sets the time between two snapshots
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    fn poll(&mut self) -> Result<()> {
+        self.polled = Instant::now();
+
+        for ctrl in (self.read)()? {
+            if !self.ids.is_empty() && !self.ids.contains(&ctrl.id) {
+                continue;
+            }
+
+            match self.known.entry(ctrl.id) {
+                Entry::Occupied(mut entry) => {
+                    let prev = entry.get();
+                    let mut changes = Changes::empty();
+                    if prev.value != ctrl.value {
+                        changes |= Changes::VALUE;
+                    }
+                    if prev.flags != ctrl.flags {
+                        changes |= Changes::FLAGS;
+                    }
+                    if prev.typ != ctrl.typ {
+                        changes |= Changes::RANGE;
+                    }
+
+                    if !changes.is_empty() {
+                        self.pending.push_back(Event::new(&ctrl, changes));
+                    }
+                    entry.insert(ctrl);
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(ctrl);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the next event, waiting at most `timeout` for it
+    ///
+    /// Returns an error of kind [`ErrorKind::Timeout`] if nothing changed in time.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<Result<Event>> {
+        self.wait(Some(timeout))
+    }
+
+    fn wait(&mut self, timeout: Option<Duration>) -> Option<Result<Event>> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(Ok(event));
+            }
+
+            let next = self.polled + self.interval;
+            if let Some(deadline) = deadline {
+                if deadline < next {
+                    thread::sleep(deadline.saturating_duration_since(Instant::now()));
+                    return Some(Err(Error::new(
+                        ErrorKind::Timeout,
+                        "timed out waiting for a control event",
+                    )));
+                }
+            }
+
+            thread::sleep(next.saturating_duration_since(Instant::now()));
+            if let Err(e) = self.poll() {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Poller<'a> {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.wait(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    fn numeric(range: (f64, f64), step: f32) -> Descriptor {
+        Descriptor {
+            id: 1,
+            name: String::from("Number"),
+            typ: Type::Number { range, step },
+            flags: Flags::READ | Flags::WRITE,
+            kind: None,
+            scale: None,
+            default: None,
+            value: None,
+        }
+    }
+
+    fn menu(indices: &[u32]) -> Descriptor {
+        let items = indices
+            .iter()
+            .map(|index| MenuItem {
+                index: *index,
+                value: MenuValue::Number(*index as f64),
+            })
+            .collect();
+
+        Descriptor {
+            typ: Type::Menu(items),
+            ..numeric((0.0, 0.0), 0.0)
+        }
+    }
+
+    #[test]
+    fn validate_number() {
+        let ctrl = numeric((-10.0, 10.0), 2.0);
+        assert!(ctrl.validate(&State::Number(-10.0)).is_ok());
+        assert!(ctrl.validate(&State::Integer(4)).is_ok());
+        assert!(ctrl.validate(&State::Number(10.0)).is_ok());
+
+        let err = ctrl.validate(&State::Number(11.0)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidArgument);
+        let err = ctrl.validate(&State::Integer(3)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidArgument);
+        let err = ctrl.validate(&State::Boolean(true)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidArgument);
+    }
+
+    #[test]
+    fn validate_menu() {
+        let ctrl = menu(&[0, 2, 5]);
+        assert!(ctrl.validate(&State::Menu(2)).is_ok());
+        assert!(ctrl.validate(&State::Integer(5)).is_ok());
+        assert!(ctrl.validate(&State::Menu(1)).is_err());
+    }
+
+    #[test]
+    fn validate_flags() {
+        let ctrl = Descriptor {
+            flags: Flags::READ,
+            ..numeric((0.0, 1.0), 1.0)
+        };
+        let err = ctrl.validate(&State::Number(1.0)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidArgument);
+
+        let ctrl = Descriptor {
+            flags: Flags::READ | Flags::WRITE | Flags::INACTIVE,
+            ..numeric((0.0, 1.0), 1.0)
+        };
+        let err = ctrl.validate(&State::Number(1.0)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotSupported);
+    }
+
+    #[test]
+    fn clamp_number() {
+        let ctrl = numeric((0.0, 10.0), 3.0);
+        assert_eq!(
+            ctrl.clamp(&State::Number(-5.0)).unwrap(),
+            State::Number(0.0)
+        );
+        assert_eq!(ctrl.clamp(&State::Number(4.0)).unwrap(), State::Number(3.0));
+        assert_eq!(ctrl.clamp(&State::Integer(5)).unwrap(), State::Integer(6));
+        // 10 is off-step, the closest step within the range is 9
+        assert_eq!(
+            ctrl.clamp(&State::Number(42.0)).unwrap(),
+            State::Number(9.0)
+        );
+
+        let ctrl = numeric((0.0, 1.0), 0.0);
+        assert_eq!(ctrl.clamp(&State::Number(0.3)).unwrap(), State::Number(0.3));
+    }
+
+    #[test]
+    fn clamp_other() {
+        let ctrl = menu(&[0, 1]);
+        assert_eq!(ctrl.clamp(&State::Menu(1)).unwrap(), State::Menu(1));
+        assert!(ctrl.clamp(&State::Menu(2)).is_err());
+    }
+
+    #[test]
+    fn poller() {
+        let value = Rc::new(Cell::new(0.0));
+        let read = {
+            let value = value.clone();
+            move || {
+                Ok(vec![
+                    Descriptor {
+                        value: Some(State::Number(value.get())),
+                        ..numeric((0.0, 10.0), 1.0)
+                    },
+                    Descriptor {
+                        id: 2,
+                        value: Some(State::Number(value.get())),
+                        ..numeric((0.0, 10.0), 1.0)
+                    },
+                ])
+            }
+        };
+        let mut poller = Poller::new(read, &[1])
+            .unwrap()
+            .interval(Duration::from_millis(1));
+
+        // nothing changed since the initial snapshot
+        let err = poller
+            .next_timeout(Duration::from_millis(20))
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Timeout);
+
+        value.set(5.0);
+        let event = poller.next().unwrap().unwrap();
+        assert_eq!(event.id, 1);
+        assert_eq!(event.changes, Changes::VALUE);
+        assert_eq!(event.value, Some(State::Number(5.0)));
+        assert_eq!(event.range, Some((0.0, 10.0)));
+
+        // control 2 is not watched
+        let err = poller
+            .next_timeout(Duration::from_millis(20))
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Timeout);
+    }
 }
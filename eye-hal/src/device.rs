@@ -1,3 +1,5 @@
+use crate::stream::Io;
+
 #[derive(Clone, Debug)]
 /// Device description
 pub struct Description {
@@ -5,4 +7,41 @@ pub struct Description {
     pub uri: String,
     /// Human-readable product name
     pub product: String,
+    /// I/O methods supported by the device, empty if the backend cannot tell
+    pub io: Vec<Io>,
+}
+
+#[derive(Clone, Debug)]
+/// Hotplug event
+pub enum Event {
+    /// A device was connected
+    Added(Description),
+    /// A device was disconnected
+    Removed(Description),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// Device access mode
+///
+/// Clients with a higher priority may prevent others from changing the device configuration.
+pub enum Access {
+    /// Lowest priority, yield to all other clients
+    Background,
+    /// Regular priority, shared with other clients
+    #[default]
+    Shared,
+    /// Highest priority, other clients can neither configure the device nor claim exclusive
+    /// access themselves
+    Exclusive,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Device state
+pub enum State {
+    /// Nobody is capturing from the device
+    Idle,
+    /// A stream is set up, possibly by this client
+    Streaming,
+    /// Another client has exclusive access
+    Busy,
 }
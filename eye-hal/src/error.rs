@@ -29,14 +29,30 @@ impl fmt::Debug for Repr {
 
 #[derive(Debug)]
 struct Custom {
-    _kind: ErrorKind,
+    kind: ErrorKind,
     error: Box<dyn error::Error + Send + Sync>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum ErrorKind {
     /// This operation is not supported.
     NotSupported,
+    /// An argument was invalid, e.g. a value out of range.
+    InvalidArgument,
+    /// The operation did not complete in time.
+    Timeout,
+    /// The device or resource is busy.
+    Busy,
+    /// The device is owned by another client, e.g. because it is streaming or the client
+    /// claimed exclusive access.
+    InUse,
+    /// The caller lacks the permissions to access the device.
+    PermissionDenied,
+    /// The device or entity does not exist.
+    NotFound,
+    /// The device is gone, e.g. because it was unplugged.
+    Disconnected,
     /// Any other error not part of this list.
     Other,
 }
@@ -45,6 +61,13 @@ impl fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             ErrorKind::NotSupported => write!(f, "not supported"),
+            ErrorKind::InvalidArgument => write!(f, "invalid argument"),
+            ErrorKind::Timeout => write!(f, "timed out"),
+            ErrorKind::Busy => write!(f, "device busy"),
+            ErrorKind::InUse => write!(f, "in use by another client"),
+            ErrorKind::PermissionDenied => write!(f, "permission denied"),
+            ErrorKind::NotFound => write!(f, "not found"),
+            ErrorKind::Disconnected => write!(f, "disconnected"),
             ErrorKind::Other => write!(f, "other"),
         }
     }
@@ -57,11 +80,19 @@ impl Error {
     {
         Error {
             repr: Repr::Custom(Box::new(Custom {
-                _kind: kind,
+                kind,
                 error: error.into(),
             })),
         }
     }
+
+    /// Returns the corresponding [`ErrorKind`] for this error.
+    pub fn kind(&self) -> ErrorKind {
+        match &self.repr {
+            Repr::Simple(kind) => *kind,
+            Repr::Custom(c) => c.kind,
+        }
+    }
 }
 
 impl From<ErrorKind> for Error {
@@ -74,9 +105,19 @@ impl From<ErrorKind> for Error {
 
 impl From<io::Error> for Error {
     fn from(error: io::Error) -> Self {
+        // backends map their OS specific error codes themselves
+        let kind = match error.kind() {
+            io::ErrorKind::NotFound => ErrorKind::NotFound,
+            io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+            io::ErrorKind::InvalidInput => ErrorKind::InvalidArgument,
+            io::ErrorKind::TimedOut => ErrorKind::Timeout,
+            io::ErrorKind::Unsupported => ErrorKind::NotSupported,
+            _ => ErrorKind::Other,
+        };
+
         Error {
             repr: Repr::Custom(Box::new(Custom {
-                _kind: ErrorKind::Other,
+                kind,
                 error: error.into(),
             })),
         }
@@ -93,7 +134,12 @@ impl fmt::Display for Error {
 }
 
 impl error::Error for Error {
-    fn cause(&self) -> Option<&dyn std::error::Error> {
-        None
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        // like io::Error, we are transparent: Display forwards to the wrapped error, so its source
+        // is the next link in the chain
+        match &self.repr {
+            Repr::Simple(_) => None,
+            Repr::Custom(ref c) => c.error.source(),
+        }
     }
 }
@@ -59,6 +59,8 @@ pub struct ImageFormat {
     pub pixfmt: PixelFormat,
     /// Length of a pixel row in bytes
     pub stride: Option<usize>,
+    /// Size of an image in bytes
+    pub size: Option<usize>,
 }
 
 impl ImageFormat {
@@ -78,12 +80,14 @@ impl ImageFormat {
     /// ```
     pub fn new(width: u32, height: u32, pixfmt: PixelFormat) -> Self {
         let stride = pixfmt.bits().map(|bits| (width * (bits / 8)) as usize);
+        let size = stride.map(|stride| stride * height as usize);
 
         ImageFormat {
             width,
             height,
             pixfmt,
             stride,
+            size,
         }
     }
 
@@ -96,4 +100,14 @@ impl ImageFormat {
         self.stride = Some(stride);
         self
     }
+
+    /// Builder pattern constructor
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - Size of an image in bytes
+    pub fn size(mut self, size: usize) -> Self {
+        self.size = Some(size);
+        self
+    }
 }
@@ -70,6 +70,7 @@ pub mod control;
 pub mod device;
 pub mod error;
 pub mod format;
+pub mod profile;
 pub mod stream;
 pub mod traits;
 
@@ -2,10 +2,21 @@
 //!
 //! Multiple backends can be implemented for a given platform.
 
+use std::time::Duration;
+#[cfg(feature = "async")]
+use std::{
+    pin::Pin,
+    task::{Context as TaskContext, Poll},
+};
+
 use crate::control;
 use crate::device;
-use crate::error::Result;
-use crate::stream::Descriptor as StreamDescriptor;
+use crate::error::{Error, ErrorKind, Result};
+use crate::format::ImageFormat;
+use crate::stream::{
+    Buffers as StreamBuffers, Descriptor as StreamDescriptor, Fraction, Frame,
+    Options as StreamOptions, OwnedFrame, Pool, RangeDescriptor as StreamRangeDescriptor,
+};
 use crate::traits::{Context as ContextTrait, Device as DeviceTrait, Stream as StreamTrait};
 
 #[cfg(target_os = "linux")]
@@ -48,6 +59,39 @@ impl<'a> Context<'a> {
             Context::OpenPnP(openpnp::context::Context {}),
         ])
     }
+
+    /// Returns a monitor which reports devices being connected and disconnected
+    ///
+    /// The monitor yields [`device::Event`]s as they happen, so there is no need to periodically
+    /// call [`ContextTrait::devices`] to detect new devices.
+    pub fn monitor(&self) -> Result<Monitor> {
+        match self {
+            #[cfg(target_os = "linux")]
+            Self::V4l2(ctx) => Ok(Monitor::V4l2(ctx.monitor()?)),
+            _ => Err(Error::from(ErrorKind::NotSupported)),
+        }
+    }
+
+    /// Opens a device with the given access mode
+    ///
+    /// Like [`ContextTrait::open_device`], but allows for claiming exclusive access or yielding
+    /// to other clients. Only the shared mode is supported by backends other than v4l2.
+    ///
+    /// # Arguments
+    ///
+    /// * `uri` - Device URI
+    /// * `access` - Access mode
+    pub fn open_device_with(&self, uri: &str, access: device::Access) -> Result<Device<'a>> {
+        match self {
+            #[cfg(target_os = "linux")]
+            Self::V4l2(ctx) => Ok(Device::V4l2(ctx.open_device_with(uri, access)?)),
+            _ if access == device::Access::Shared => self.open_device(uri),
+            _ => Err(Error::new(
+                ErrorKind::NotSupported,
+                "access modes are not supported by this context",
+            )),
+        }
+    }
 }
 
 impl<'a> Default for Context<'a> {
@@ -86,6 +130,85 @@ impl<'a> ContextTrait<'a> for Context<'a> {
     }
 }
 
+/// Platform hotplug monitor
+///
+/// Leaky abstraction: if you require access to platform specific features, match the enum instance
+/// to get the underlying HAL implementation.
+///
+/// Iterating a monitor blocks until the next event occurs.
+pub enum Monitor {
+    /// Can be used to wrap your own struct
+    Custom(Box<dyn Iterator<Item = Result<device::Event>> + Send>),
+    #[cfg(target_os = "linux")]
+    /// Video4Linux2 monitor
+    V4l2(v4l2::monitor::Monitor),
+}
+
+impl Monitor {
+    /// Returns the next event, waiting at most `timeout` for it
+    ///
+    /// Returns an error of kind [`ErrorKind::Timeout`] if nothing happened in time.
+    pub fn next_timeout(&mut self, _timeout: Duration) -> Option<Result<device::Event>> {
+        match self {
+            Self::Custom(_) => Some(Err(Error::new(
+                ErrorKind::NotSupported,
+                "timeouts are not supported by custom monitors",
+            ))),
+            #[cfg(target_os = "linux")]
+            Self::V4l2(monitor) => monitor.next_timeout(_timeout),
+        }
+    }
+}
+
+impl Iterator for Monitor {
+    type Item = Result<device::Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Custom(monitor) => monitor.next(),
+            #[cfg(target_os = "linux")]
+            Self::V4l2(monitor) => monitor.next(),
+        }
+    }
+}
+
+/// Control event subscription
+///
+/// Leaky abstraction: if you require access to platform specific features, match the enum instance
+/// to get the underlying HAL implementation.
+pub enum ControlEvents<'a> {
+    /// Events emulated by polling the controls
+    Poll(control::Poller<'a>),
+    #[cfg(target_os = "linux")]
+    /// Video4Linux2 control event subscription
+    V4l2(v4l2::control::Subscription),
+}
+
+impl<'a> ControlEvents<'a> {
+    /// Returns the next event, waiting at most `timeout` for it
+    ///
+    /// Returns an error of kind [`ErrorKind::Timeout`] if nothing changed in time.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<Result<control::Event>> {
+        match self {
+            Self::Poll(events) => events.next_timeout(timeout),
+            #[cfg(target_os = "linux")]
+            Self::V4l2(events) => events.next_timeout(timeout),
+        }
+    }
+}
+
+impl<'a> Iterator for ControlEvents<'a> {
+    type Item = Result<control::Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Poll(events) => events.next(),
+            #[cfg(target_os = "linux")]
+            Self::V4l2(events) => events.next(),
+        }
+    }
+}
+
 /// Platform device
 ///
 /// Leaky abstraction: if you require access to platform specific features, match the enum instance
@@ -100,12 +223,118 @@ pub enum Device<'a> {
     V4l2(v4l2::device::Handle),
     #[cfg(any(target_os = "windows", feature = "plat-uvc"))]
     /// Universal Video Class device handle
-    Uvc(uvc::device::Handle<'a>),
+    Uvc(uvc::device::Handle),
     #[cfg(any(target_os = "macos", feature = "plat-openpnp"))]
     /// Open Pick and Place
     OpenPnP(openpnp::device::Handle),
 }
 
+impl<'a> Device<'a> {
+    /// Subscribes to control change events
+    ///
+    /// Backends which do not publish control events are polled instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - Control identifiers, all controls are watched if empty
+    pub fn subscribe_controls(&self, ids: &[u32]) -> Result<ControlEvents<'a>> {
+        match self {
+            Self::Custom(_) => Err(Error::new(
+                ErrorKind::NotSupported,
+                "control events are not supported by custom devices",
+            )),
+            #[cfg(target_os = "linux")]
+            Self::V4l2(dev) => Ok(ControlEvents::V4l2(dev.subscribe_controls(ids)?)),
+            #[cfg(any(target_os = "windows", feature = "plat-uvc"))]
+            Self::Uvc(dev) => Ok(ControlEvents::Poll(dev.subscribe_controls(ids)?)),
+            #[cfg(any(target_os = "macos", feature = "plat-openpnp"))]
+            Self::OpenPnP(dev) => Ok(ControlEvents::Poll(dev.subscribe_controls(ids)?)),
+        }
+    }
+
+    /// Returns whether the device is streaming or owned by another client
+    ///
+    /// Only supported by the v4l2 backend.
+    pub fn state(&self) -> Result<device::State> {
+        match self {
+            #[cfg(target_os = "linux")]
+            Self::V4l2(dev) => dev.state(),
+            _ => Err(Error::new(
+                ErrorKind::NotSupported,
+                "state queries are not supported by this device",
+            )),
+        }
+    }
+
+    /// Returns a stream which produces images
+    ///
+    /// Like [`DeviceTrait::start_stream`], but allows for tuning the stream setup.
+    ///
+    /// # Arguments
+    ///
+    /// * `desc` - Stream descriptor
+    /// * `opts` - Stream options
+    pub fn start_stream_with(
+        &self,
+        desc: &StreamDescriptor,
+        opts: &StreamOptions,
+    ) -> Result<Stream<'a>> {
+        if opts.buffers == Some(0) {
+            return Err(Error::new(
+                ErrorKind::InvalidArgument,
+                "at least one buffer is required",
+            ));
+        }
+
+        match self {
+            Self::Custom(dev) => {
+                if *opts != StreamOptions::default() {
+                    return Err(Error::new(
+                        ErrorKind::NotSupported,
+                        "stream options are not supported by custom devices",
+                    ));
+                }
+                dev.start_stream(desc)
+            }
+            #[cfg(target_os = "linux")]
+            Self::V4l2(dev) => Ok(Stream::V4l2(dev.start_stream_with(desc, opts)?)),
+            #[cfg(any(target_os = "windows", feature = "plat-uvc"))]
+            Self::Uvc(dev) => Ok(Stream::Uvc(dev.start_stream_with(desc, opts)?)),
+            #[cfg(any(target_os = "macos", feature = "plat-openpnp"))]
+            Self::OpenPnP(dev) => Ok(Stream::OpenPnP(dev.start_stream_with(desc, opts)?)),
+        }
+    }
+
+    /// Returns a stream which captures into the given buffers
+    ///
+    /// Like [`Device::start_stream_with`], but the stream uses memory supplied by the caller
+    /// instead of allocating its own. Only supported by the v4l2 backend.
+    ///
+    /// # Arguments
+    ///
+    /// * `desc` - Stream descriptor
+    /// * `opts` - Stream options
+    /// * `bufs` - Buffers to capture into
+    pub fn start_stream_into(
+        &self,
+        desc: &StreamDescriptor,
+        opts: &StreamOptions,
+        bufs: StreamBuffers,
+    ) -> Result<Stream<'a>> {
+        match self {
+            #[cfg(target_os = "linux")]
+            Self::V4l2(dev) => Ok(Stream::V4l2(dev.start_stream_into(desc, opts, bufs)?)),
+            _ => {
+                let _ = (desc, opts, bufs);
+                Err(Error::new(
+                    ErrorKind::NotSupported,
+                    "caller supplied buffers are not supported by this device",
+                ))
+            }
+        }
+    }
+}
+
 impl<'a> DeviceTrait<'a> for Device<'a> {
     type Stream = Stream<'a>;
 
@@ -121,6 +350,18 @@ impl<'a> DeviceTrait<'a> for Device<'a> {
         }
     }
 
+    fn stream_ranges(&self) -> Result<Vec<StreamRangeDescriptor>> {
+        match self {
+            Self::Custom(dev) => dev.stream_ranges(),
+            #[cfg(target_os = "linux")]
+            Self::V4l2(dev) => dev.stream_ranges(),
+            #[cfg(any(target_os = "windows", feature = "plat-uvc"))]
+            Self::Uvc(dev) => dev.stream_ranges(),
+            #[cfg(any(target_os = "macos", feature = "plat-openpnp"))]
+            Self::OpenPnP(dev) => dev.stream_ranges(),
+        }
+    }
+
     fn controls(&self) -> Result<Vec<control::Descriptor>> {
         match self {
             Self::Custom(dev) => dev.controls(),
@@ -133,6 +374,30 @@ impl<'a> DeviceTrait<'a> for Device<'a> {
         }
     }
 
+    fn control_descriptor(&self, id: u32) -> Result<control::Descriptor> {
+        match self {
+            Self::Custom(dev) => dev.control_descriptor(id),
+            #[cfg(target_os = "linux")]
+            Self::V4l2(dev) => dev.control_descriptor(id),
+            #[cfg(any(target_os = "windows", feature = "plat-uvc"))]
+            Self::Uvc(dev) => dev.control_descriptor(id),
+            #[cfg(any(target_os = "macos", feature = "plat-openpnp"))]
+            Self::OpenPnP(dev) => dev.control_descriptor(id),
+        }
+    }
+
+    fn find_control(&self, kind: control::Kind) -> Result<Option<control::Descriptor>> {
+        match self {
+            Self::Custom(dev) => dev.find_control(kind),
+            #[cfg(target_os = "linux")]
+            Self::V4l2(dev) => dev.find_control(kind),
+            #[cfg(any(target_os = "windows", feature = "plat-uvc"))]
+            Self::Uvc(dev) => dev.find_control(kind),
+            #[cfg(any(target_os = "macos", feature = "plat-openpnp"))]
+            Self::OpenPnP(dev) => dev.find_control(kind),
+        }
+    }
+
     fn control(&self, id: u32) -> Result<control::State> {
         match self {
             Self::Custom(dev) => dev.control(id),
@@ -157,6 +422,30 @@ impl<'a> DeviceTrait<'a> for Device<'a> {
         }
     }
 
+    fn set_controls(&mut self, ctrls: &[(u32, control::State)]) -> Result<()> {
+        match self {
+            Self::Custom(dev) => dev.set_controls(ctrls),
+            #[cfg(target_os = "linux")]
+            Self::V4l2(dev) => dev.set_controls(ctrls),
+            #[cfg(any(target_os = "windows", feature = "plat-uvc"))]
+            Self::Uvc(dev) => dev.set_controls(ctrls),
+            #[cfg(any(target_os = "macos", feature = "plat-openpnp"))]
+            Self::OpenPnP(dev) => dev.set_controls(ctrls),
+        }
+    }
+
+    fn reset_controls(&mut self) -> Result<()> {
+        match self {
+            Self::Custom(dev) => dev.reset_controls(),
+            #[cfg(target_os = "linux")]
+            Self::V4l2(dev) => dev.reset_controls(),
+            #[cfg(any(target_os = "windows", feature = "plat-uvc"))]
+            Self::Uvc(dev) => dev.reset_controls(),
+            #[cfg(any(target_os = "macos", feature = "plat-openpnp"))]
+            Self::OpenPnP(dev) => dev.reset_controls(),
+        }
+    }
+
     fn start_stream(&self, desc: &StreamDescriptor) -> Result<Self::Stream> {
         match self {
             Self::Custom(dev) => dev.start_stream(desc),
@@ -180,20 +469,161 @@ impl<'a> DeviceTrait<'a> for Device<'a> {
 /// the best method available.
 pub enum Stream<'a> {
     /// Can be used to wrap your own struct
-    Custom(Box<dyn 'a + for<'b> StreamTrait<'b, Item = Result<&'b [u8]>> + Send>),
+    Custom(Box<dyn 'a + for<'b> StreamTrait<'b, Item = Result<Frame<'b>>> + Send>),
     #[cfg(target_os = "linux")]
     /// Video4Linux2 stream handle
-    V4l2(v4l2::stream::Handle<'a>),
+    V4l2(v4l2::stream::Handle),
     #[cfg(any(target_os = "windows", feature = "plat-uvc"))]
     /// Universal Video Class stream handle
-    Uvc(uvc::stream::Handle<'a>),
+    Uvc(uvc::stream::Handle),
     #[cfg(any(target_os = "macos", feature = "plat-openpnp"))]
     /// Open Pick and Place
     OpenPnP(openpnp::stream::Handle),
 }
 
+impl<'a> Stream<'a> {
+    /// Returns the image format of the frames
+    ///
+    /// This is the format negotiated with the driver, which may differ from the one requested
+    /// when the stream was started.
+    pub fn format(&self) -> Result<ImageFormat> {
+        match self {
+            Self::Custom(_) => Err(Error::from(ErrorKind::NotSupported)),
+            #[cfg(target_os = "linux")]
+            Self::V4l2(stream) => Ok(stream.format().clone()),
+            #[cfg(any(target_os = "windows", feature = "plat-uvc"))]
+            Self::Uvc(stream) => Ok(stream.format().clone()),
+            #[cfg(any(target_os = "macos", feature = "plat-openpnp"))]
+            Self::OpenPnP(stream) => Ok(stream.format().clone()),
+        }
+    }
+
+    /// Returns the frame interval negotiated with the driver
+    pub fn interval(&self) -> Result<Fraction> {
+        match self {
+            Self::Custom(_) => Err(Error::from(ErrorKind::NotSupported)),
+            #[cfg(target_os = "linux")]
+            Self::V4l2(stream) => Ok(stream.interval()),
+            #[cfg(any(target_os = "windows", feature = "plat-uvc"))]
+            Self::Uvc(stream) => Ok(stream.interval()),
+            #[cfg(any(target_os = "macos", feature = "plat-openpnp"))]
+            Self::OpenPnP(stream) => Ok(stream.interval()),
+        }
+    }
+
+    /// Returns the number of buffers granted by the backend
+    ///
+    /// This may differ from the count requested through [`StreamOptions::buffers`].
+    pub fn buffers(&self) -> Result<usize> {
+        match self {
+            Self::Custom(_) => Err(Error::from(ErrorKind::NotSupported)),
+            #[cfg(target_os = "linux")]
+            Self::V4l2(stream) => Ok(stream.buffers()),
+            #[cfg(any(target_os = "windows", feature = "plat-uvc"))]
+            Self::Uvc(stream) => Ok(stream.buffers()),
+            #[cfg(any(target_os = "macos", feature = "plat-openpnp"))]
+            Self::OpenPnP(stream) => Ok(stream.buffers()),
+        }
+    }
+
+    /// Advances the stream, waiting at most `timeout` for the next frame
+    ///
+    /// Returns an error of kind [`ErrorKind::Timeout`] if no frame arrived in time, e.g. because
+    /// the device stalled or was unplugged.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<Result<Frame<'_>>> {
+        match self {
+            Self::Custom(_) => Some(Err(Error::new(
+                ErrorKind::NotSupported,
+                "timeouts are not supported by custom streams",
+            ))),
+            #[cfg(target_os = "linux")]
+            Self::V4l2(stream) => stream.next_timeout(timeout),
+            #[cfg(any(target_os = "windows", feature = "plat-uvc"))]
+            Self::Uvc(stream) => stream.next_timeout(timeout),
+            #[cfg(any(target_os = "macos", feature = "plat-openpnp"))]
+            Self::OpenPnP(stream) => stream.next_timeout(timeout),
+        }
+    }
+
+    /// Returns the next frame if one is ready, without waiting
+    ///
+    /// Returns an error of kind [`ErrorKind::Timeout`] if no frame is ready.
+    pub fn try_next(&mut self) -> Option<Result<Frame<'_>>> {
+        match self {
+            Self::Custom(_) => Some(Err(Error::new(
+                ErrorKind::NotSupported,
+                "polling is not supported by custom streams",
+            ))),
+            #[cfg(target_os = "linux")]
+            Self::V4l2(stream) => stream.try_next(),
+            #[cfg(any(target_os = "windows", feature = "plat-uvc"))]
+            Self::Uvc(stream) => stream.try_next(),
+            #[cfg(any(target_os = "macos", feature = "plat-openpnp"))]
+            Self::OpenPnP(stream) => stream.try_next(),
+        }
+    }
+
+    /// Advances the stream and returns the next frame as an owned buffer
+    ///
+    /// Owned frames are leased from a fixed pool of buffers and stay valid until they are
+    /// dropped, at which point their buffer is returned to the pool. This allows for keeping
+    /// several frames in flight, e.g. by sending them to worker threads. Where possible, the
+    /// native buffers of the backend are used so no data is copied. Frames of custom streams
+    /// are always copied.
+    pub fn next_owned(&mut self) -> Option<Result<OwnedFrame>> {
+        match self {
+            Self::Custom(stream) => {
+                // custom streams only lend out their frames until the next call
+                let frame = match stream.next()? {
+                    Ok(frame) => frame,
+                    Err(e) => return Some(Err(e)),
+                };
+                Some(Pool::new(1).lease(&frame))
+            }
+            #[cfg(target_os = "linux")]
+            Self::V4l2(stream) => stream.next_owned(),
+            #[cfg(any(target_os = "windows", feature = "plat-uvc"))]
+            Self::Uvc(stream) => stream.next_owned(),
+            #[cfg(any(target_os = "macos", feature = "plat-openpnp"))]
+            Self::OpenPnP(stream) => stream.next_owned(),
+        }
+    }
+
+    /// Exports the stream buffers as DMABUF file descriptors
+    ///
+    /// The descriptors can be imported by other devices, e.g. a GPU or an encoder, to access
+    /// the frames without copying them. They are ordered by buffer index, see
+    /// [`crate::stream::Metadata::buffer`].
+    #[cfg(unix)]
+    pub fn export(&self) -> Result<Vec<std::os::fd::OwnedFd>> {
+        match self {
+            #[cfg(target_os = "linux")]
+            Self::V4l2(stream) => stream.export(),
+            _ => Err(Error::new(
+                ErrorKind::NotSupported,
+                "buffer export is not supported by this stream",
+            )),
+        }
+    }
+
+    /// Turns the stream into an asynchronous stream of owned frames
+    ///
+    /// Fails with [`ErrorKind::NotSupported`] for custom streams, which cannot wake up a task.
+    #[cfg(feature = "async")]
+    pub fn into_async(self) -> Result<AsyncStream<'a>> {
+        if let Self::Custom(_) = self {
+            return Err(Error::new(
+                ErrorKind::NotSupported,
+                "async capture is not supported by custom streams",
+            ));
+        }
+
+        Ok(AsyncStream { inner: self })
+    }
+}
+
 impl<'a, 'b> StreamTrait<'b> for Stream<'a> {
-    type Item = Result<&'b [u8]>;
+    type Item = Result<Frame<'b>>;
 
     fn next(&'b mut self) -> Option<Self::Item> {
         match self {
@@ -207,3 +637,43 @@ impl<'a, 'b> StreamTrait<'b> for Stream<'a> {
         }
     }
 }
+
+#[cfg(feature = "async")]
+/// Asynchronous platform stream
+///
+/// Implements [`futures_core::Stream`], yielding owned frames (see [`Stream::next_owned`]).
+/// Waiting for a frame never blocks the executor thread: V4L2 streams wait for the device to
+/// become readable, libuvc streams are woken up by the frame callback and openpnp streams are
+/// polled periodically using a timer.
+///
+/// The stream does not depend on a particular runtime, so it can be driven by e.g. tokio or
+/// async-std alike.
+pub struct AsyncStream<'a> {
+    inner: Stream<'a>,
+}
+
+#[cfg(feature = "async")]
+impl<'a> AsyncStream<'a> {
+    /// Returns the wrapped platform stream
+    pub fn into_inner(self) -> Stream<'a> {
+        self.inner
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'a> futures_core::Stream for AsyncStream<'a> {
+    type Item = Result<OwnedFrame>;
+
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        match &mut self.get_mut().inner {
+            // rejected by Stream::into_async
+            Stream::Custom(_) => Poll::Ready(None),
+            #[cfg(target_os = "linux")]
+            Stream::V4l2(stream) => stream.poll_next_owned(_cx),
+            #[cfg(any(target_os = "windows", feature = "plat-uvc"))]
+            Stream::Uvc(stream) => stream.poll_next_owned(_cx),
+            #[cfg(any(target_os = "macos", feature = "plat-openpnp"))]
+            Stream::OpenPnP(stream) => stream.poll_next_owned(_cx),
+        }
+    }
+}
@@ -17,6 +17,7 @@ impl<'a> ContextTrait<'a> for Context {
             .map(|i| device::Description {
                 uri: format!("pnp://{}", i),
                 product: "Unknown OpenPnP device".to_string(),
+                io: Vec::new(),
             })
             .collect();
 
@@ -8,31 +8,103 @@ pub(crate) enum Typ {
     Auto,
 }
 
-const ALL: [(u32, &str, Typ); 17] = [
-    (sys::CAPPROPID_EXPOSURE, "Exposure", Typ::Limited),
-    (sys::CAPPROPID_EXPOSURE, "Auto Exposure", Typ::Auto),
-    (sys::CAPPROPID_FOCUS, "Focus", Typ::Limited),
-    (sys::CAPPROPID_FOCUS, "Auto Focus", Typ::Auto),
-    (sys::CAPPROPID_ZOOM, "Zoom", Typ::Limited),
-    (sys::CAPPROPID_WHITEBALANCE, "White Balance", Typ::Limited),
-    (sys::CAPPROPID_WHITEBALANCE, "Auto White Balance", Typ::Auto),
-    (sys::CAPPROPID_GAIN, "Gain", Typ::Limited),
-    (sys::CAPPROPID_GAIN, "Auto Gain", Typ::Auto),
-    (sys::CAPPROPID_BRIGHTNESS, "Brightness", Typ::Limited),
-    (sys::CAPPROPID_CONTRAST, "Contrast", Typ::Limited),
-    (sys::CAPPROPID_SATURATION, "Saturation", Typ::Limited),
-    (sys::CAPPROPID_GAMMA, "Gamma", Typ::Limited),
-    (sys::CAPPROPID_HUE, "Hue", Typ::Limited),
-    (sys::CAPPROPID_SHARPNESS, "Sharpness", Typ::Limited),
+const ALL: [(u32, &str, Typ, control::Kind); 17] = [
+    (
+        sys::CAPPROPID_EXPOSURE,
+        "Exposure",
+        Typ::Limited,
+        control::Kind::Exposure,
+    ),
+    (
+        sys::CAPPROPID_EXPOSURE,
+        "Auto Exposure",
+        Typ::Auto,
+        control::Kind::AutoExposure,
+    ),
+    (
+        sys::CAPPROPID_FOCUS,
+        "Focus",
+        Typ::Limited,
+        control::Kind::Focus,
+    ),
+    (
+        sys::CAPPROPID_FOCUS,
+        "Auto Focus",
+        Typ::Auto,
+        control::Kind::AutoFocus,
+    ),
+    (
+        sys::CAPPROPID_ZOOM,
+        "Zoom",
+        Typ::Limited,
+        control::Kind::Zoom,
+    ),
+    (
+        sys::CAPPROPID_WHITEBALANCE,
+        "White Balance",
+        Typ::Limited,
+        control::Kind::WhiteBalance,
+    ),
+    (
+        sys::CAPPROPID_WHITEBALANCE,
+        "Auto White Balance",
+        Typ::Auto,
+        control::Kind::AutoWhiteBalance,
+    ),
+    (
+        sys::CAPPROPID_GAIN,
+        "Gain",
+        Typ::Limited,
+        control::Kind::Gain,
+    ),
+    (
+        sys::CAPPROPID_GAIN,
+        "Auto Gain",
+        Typ::Auto,
+        control::Kind::AutoGain,
+    ),
+    (
+        sys::CAPPROPID_BRIGHTNESS,
+        "Brightness",
+        Typ::Limited,
+        control::Kind::Brightness,
+    ),
+    (
+        sys::CAPPROPID_CONTRAST,
+        "Contrast",
+        Typ::Limited,
+        control::Kind::Contrast,
+    ),
+    (
+        sys::CAPPROPID_SATURATION,
+        "Saturation",
+        Typ::Limited,
+        control::Kind::Saturation,
+    ),
+    (
+        sys::CAPPROPID_GAMMA,
+        "Gamma",
+        Typ::Limited,
+        control::Kind::Gamma,
+    ),
+    (sys::CAPPROPID_HUE, "Hue", Typ::Limited, control::Kind::Hue),
+    (
+        sys::CAPPROPID_SHARPNESS,
+        "Sharpness",
+        Typ::Limited,
+        control::Kind::Sharpness,
+    ),
     (
         sys::CAPPROPID_BACKLIGHTCOMP,
         "Backlight Compensation",
         Typ::Limited,
+        control::Kind::BacklightCompensation,
     ),
     (
         sys::CAPPROPID_POWERLINEFREQ,
         "Powerline Frequency",
         Typ::Limited,
+        control::Kind::PowerLineFrequency,
     ),
 ];
 
@@ -42,7 +114,7 @@ pub fn all(
 ) -> impl IntoIterator<Item = control::Descriptor> {
     ALL.iter()
         .enumerate()
-        .filter_map(move |(i, (id, name, typ))| {
+        .filter_map(move |(i, (id, name, typ, kind))| {
             // check whether the control is available and parse its properties
             match typ {
                 Typ::Limited => unsafe {
@@ -55,15 +127,28 @@ pub fn all(
                         &mut max,
                         &mut default,
                     ) {
-                        sys::CAPRESULT_OK => Some(control::Descriptor {
-                            id: i as u32,
-                            name: name.to_string(),
-                            typ: control::Type::Number {
-                                range: (min as f64, max as f64),
-                                step: 1.0,
-                            },
-                            flags: control::Flags::READ | control::Flags::WRITE,
-                        }),
+                        sys::CAPRESULT_OK => {
+                            let mut value = 0;
+                            let value = match sys::Cap_getProperty(ctx, stream, *id, &mut value) {
+                                sys::CAPRESULT_OK => Some(control::State::Number(value as f64)),
+                                _ => None,
+                            };
+
+                            Some(control::Descriptor {
+                                id: i as u32,
+                                name: name.to_string(),
+                                typ: control::Type::Number {
+                                    range: (min as f64, max as f64),
+                                    step: 1.0,
+                                },
+                                flags: control::Flags::READ | control::Flags::WRITE,
+                                kind: Some(*kind),
+                                // openpnp values are in arbitrary device units
+                                scale: None,
+                                default: Some(control::State::Number(default as f64)),
+                                value,
+                            })
+                        }
                         _ => {
                             // either the property is not available or there was an error
                             None
@@ -79,6 +164,11 @@ pub fn all(
                             name: name.to_string(),
                             typ: control::Type::Boolean,
                             flags: control::Flags::READ | control::Flags::WRITE,
+                            kind: Some(*kind),
+                            scale: None,
+                            // openpnp does not report whether automatic mode is on by default
+                            default: None,
+                            value: Some(control::State::Boolean(on_off != 0)),
                         }),
                         _ => {
                             // either the property is not available or there was an error
@@ -91,7 +181,10 @@ pub fn all(
 }
 
 pub fn read(ctx: sys::CapContext, stream: sys::CapStream, id: u32) -> Result<control::State> {
-    let (id, _name, typ) = &ALL[id as usize];
+    let (id, _name, typ, _kind) = match ALL.get(id as usize) {
+        Some(ctrl) => ctrl,
+        None => return Err(Error::new(ErrorKind::NotFound, "unknown control ID")),
+    };
     match typ {
         Typ::Limited => unsafe {
             let mut value = 0;
@@ -122,7 +215,10 @@ pub fn write(
     id: u32,
     value: &control::State,
 ) -> Result<()> {
-    let (id, _name, typ) = &ALL[id as usize];
+    let (id, _name, typ, _kind) = match ALL.get(id as usize) {
+        Some(ctrl) => ctrl,
+        None => return Err(Error::new(ErrorKind::NotFound, "unknown control ID")),
+    };
     match typ {
         Typ::Limited => unsafe {
             let value = if let control::State::Number(value) = value {
@@ -1,6 +1,6 @@
 use std::cell::Cell;
 use std::convert::TryInto;
-use std::{io, time};
+use std::io;
 
 use openpnp_capture as pnp;
 use openpnp_capture_sys as sys;
@@ -52,6 +52,99 @@ impl Handle {
             Err(io::Error::new(io::ErrorKind::Other, "not a pnp:// URI!"))
         }
     }
+
+    /// Subscribes to control change events
+    ///
+    /// openpnp-capture does not report control changes, so they are detected by polling. Like
+    /// all control operations, this requires a running stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - Control identifiers, all controls are watched if empty
+    pub fn subscribe_controls(&self, ids: &[u32]) -> Result<control::Poller<'static>> {
+        let stream_id = self
+            .stream_id
+            .get()
+            .ok_or(Error::new(ErrorKind::Other, "stream not running"))?;
+
+        control::Poller::new(
+            move || {
+                let pnp_ctx = pnp::context::CONTEXT.lock().unwrap().inner;
+                Ok(pnp_ctrl::all(pnp_ctx, stream_id).into_iter().collect())
+            },
+            ids,
+        )
+    }
+
+    /// Returns a stream which produces images
+    ///
+    /// openpnp-capture picks the closest format, the effective format is reported by the stream.
+    /// Frames are always converted to RGB. If `opts.strict` is set, adjustments are treated as
+    /// error instead.
+    pub fn start_stream_with(
+        &self,
+        desc: &stream::Descriptor,
+        opts: &stream::Options,
+    ) -> Result<StreamHandle> {
+        if opts.io != stream::Io::Auto {
+            return Err(Error::new(
+                ErrorKind::NotSupported,
+                "only the default I/O method is supported",
+            ));
+        }
+        // openpnp-capture only ever holds the latest frame, there is no queue to drain
+        if opts.policy != stream::Policy::QueueAll {
+            return Err(Error::new(
+                ErrorKind::NotSupported,
+                "only the default queue policy is supported",
+            ));
+        }
+
+        let fourcc = match desc.pixfmt.clone() {
+            PixelFormat::Rgb(24) => *b"RGB3",
+            PixelFormat::Custom(repr) => {
+                if repr.len() == 4 {
+                    repr.as_bytes().try_into().unwrap()
+                } else {
+                    return Err(Error::new(ErrorKind::NotSupported, "invalid fourcc"));
+                }
+            }
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::NotSupported,
+                    "cannot map pixfmt to fourcc",
+                ))
+            }
+        };
+
+        let fmt = pnp::Format {
+            width: desc.width,
+            height: desc.height,
+            fourcc: pnp::format::FourCC::new(&fourcc),
+            bpp: 0,
+            fps: (1.0 / desc.interval.as_f64()).round() as u32,
+        };
+
+        let handle = StreamHandle::new(&self.inner, &fmt, opts)?;
+        let matched = handle.inner.format();
+        if opts.strict
+            && (matched.width != fmt.width
+                || matched.height != fmt.height
+                || matched.fps != fmt.fps
+                || (desc.pixfmt != PixelFormat::Rgb(24) && matched.fourcc != fmt.fourcc))
+        {
+            return Err(Error::new(
+                ErrorKind::NotSupported,
+                format!(
+                    "stream format adjusted to {}x{} {} @ {} fps",
+                    matched.width, matched.height, matched.fourcc, matched.fps
+                ),
+            ));
+        }
+
+        self.stream_id.set(Some(handle.inner.id()));
+        Ok(handle)
+    }
 }
 
 impl<'a> Device<'a> for Handle {
@@ -65,7 +158,7 @@ impl<'a> Device<'a> for Handle {
                 width: fmt.width,
                 height: fmt.height,
                 pixfmt: PixelFormat::Custom(fmt.fourcc.to_string()),
-                interval: time::Duration::from_secs_f32(1.0 / fmt.fps as f32),
+                interval: stream::Fraction::from_fps(fmt.fps),
             })
             .collect();
 
@@ -108,34 +201,7 @@ impl<'a> Device<'a> for Handle {
     }
 
     fn start_stream(&self, desc: &stream::Descriptor) -> Result<Self::Stream> {
-        let fourcc = match desc.pixfmt.clone() {
-            PixelFormat::Rgb(24) => *b"RGB3",
-            PixelFormat::Custom(repr) => {
-                if repr.len() == 4 {
-                    repr.as_bytes().try_into().unwrap()
-                } else {
-                    return Err(Error::new(ErrorKind::NotSupported, "invalid fourcc"));
-                }
-            }
-            _ => {
-                return Err(Error::new(
-                    ErrorKind::NotSupported,
-                    "cannot map pixfmt to fourcc",
-                ))
-            }
-        };
-
-        let fmt = pnp::Format {
-            width: desc.width,
-            height: desc.height,
-            fourcc: pnp::format::FourCC::new(&fourcc),
-            bpp: 0,
-            fps: 0,
-        };
-
-        let handle = StreamHandle::new(&self.inner, &fmt)?;
-        self.stream_id.set(Some(handle.inner.id()));
-        Ok(handle)
+        self.start_stream_with(desc, &stream::Options::default())
     }
 
     fn controls(&self) -> Result<Vec<control::Descriptor>> {
@@ -1,18 +1,42 @@
 use std::io;
+#[cfg(feature = "async")]
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
 
+#[cfg(feature = "async")]
+use async_io::Timer;
 use openpnp_capture as pnp;
 
 use crate::error::Result;
+use crate::format::{ImageFormat, PixelFormat};
+use crate::stream::{Fraction, Frame, Metadata, Options, OwnedFrame, Pool};
 use crate::traits::Stream;
 use crate::{Error, ErrorKind};
 
 pub struct Handle {
     pub(crate) inner: pnp::Stream,
     buffer: Vec<u8>,
+    sequence: u32,
+    started: Instant,
+    pool: Pool,
+    buffers: usize,
+    format: ImageFormat,
+    interval: Fraction,
+    // openpnp-capture cannot notify us about new frames, so we check periodically
+    poll_interval: Duration,
+    #[cfg(feature = "async")]
+    timer: Option<Timer>,
 }
 
 impl Handle {
-    pub fn new(dev: &pnp::Device, fmt: &pnp::Format) -> io::Result<Self> {
+    pub fn new(dev: &pnp::Device, fmt: &pnp::Format, opts: &Options) -> io::Result<Self> {
         let pnp_stream = match pnp::Stream::new(dev, fmt) {
             Some(stream) => stream,
             None => {
@@ -23,23 +47,139 @@ impl Handle {
             }
         };
 
+        // frames are always converted to RGB
+        let matched = pnp_stream.format();
+        let format = ImageFormat::new(matched.width, matched.height, PixelFormat::Rgb(24));
+        let interval = Fraction::from_fps(matched.fps);
+        // openpnp-capture only ever holds the latest frame, so the count only sizes the pool of
+        // owned frames
+        let buffers = opts.buffers.map_or(4, |count| count as usize);
+
         Ok(Handle {
             inner: pnp_stream,
             buffer: Vec::new(),
+            sequence: 0,
+            started: Instant::now(),
+            pool: Pool::new(buffers),
+            buffers,
+            format,
+            interval,
+            // check four times per frame interval
+            poll_interval: Duration::from_secs(1) / (matched.fps.max(1) * 4),
+            #[cfg(feature = "async")]
+            timer: None,
         })
     }
-}
 
-impl<'a> Stream<'a> for Handle {
-    type Item = Result<&'a [u8]>;
+    /// Returns the image format of the frames
+    pub fn format(&self) -> &ImageFormat {
+        &self.format
+    }
 
-    fn next(&'a mut self) -> Option<Self::Item> {
-        while !self.inner.poll() { /* busy loop */ }
+    /// Returns the frame interval negotiated with the device
+    pub fn interval(&self) -> Fraction {
+        self.interval
+    }
+
+    /// Returns the number of buffers available for owned frames
+    pub fn buffers(&self) -> usize {
+        self.buffers
+    }
+
+    fn read(&mut self) -> Result<Frame<'_>> {
         match self.inner.read(&mut self.buffer) {
             Ok(()) => {}
-            Err(e) => return Some(Err(Error::new(ErrorKind::Other, e))),
+            Err(e) => return Err(Error::new(ErrorKind::Other, e)),
+        }
+
+        // openpnp-capture neither timestamps nor counts frames, so we have to do it ourselves
+        let meta = Metadata {
+            timestamp: self.started.elapsed(),
+            sequence: self.sequence,
+            bytesused: self.buffer.len(),
+            corrupted: false,
+            buffer: None,
+        };
+        self.sequence = self.sequence.wrapping_add(1);
+
+        Ok(Frame::new(&self.buffer).meta(meta))
+    }
+
+    fn lease(&mut self) -> Result<OwnedFrame> {
+        let pool = self.pool.clone();
+        pool.lease(&self.read()?)
+    }
+
+    /// Advances the stream, waiting at most `timeout` for the next frame
+    ///
+    /// Returns an error of kind [`ErrorKind::Timeout`] if no frame arrived in time.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<Result<Frame<'_>>> {
+        let deadline = Instant::now() + timeout;
+        while !self.inner.poll() {
+            let now = Instant::now();
+            if now >= deadline {
+                return Some(Err(Error::new(
+                    ErrorKind::Timeout,
+                    "timed out waiting for a frame",
+                )));
+            }
+            thread::sleep(Duration::min(self.poll_interval, deadline - now));
         }
+        Some(self.read())
+    }
+
+    /// Returns the next frame if one is ready, without waiting
+    ///
+    /// Returns an error of kind [`ErrorKind::Timeout`] if no frame is ready.
+    pub fn try_next(&mut self) -> Option<Result<Frame<'_>>> {
+        if !self.inner.poll() {
+            return Some(Err(Error::new(ErrorKind::Timeout, "no frame available")));
+        }
+        Some(self.read())
+    }
+
+    /// Advances the stream and returns the next frame as an owned buffer
+    ///
+    /// Frames are copied into a fixed pool of buffers.
+    pub fn next_owned(&mut self) -> Option<Result<OwnedFrame>> {
+        while !self.inner.poll() {
+            thread::sleep(self.poll_interval);
+        }
+        Some(self.lease())
+    }
+
+    /// Attempts to pull out the next frame, registering the current task for wakeup if no frame
+    /// is available yet
+    ///
+    /// Since openpnp-capture cannot signal new frames, the task is woken up by a timer of the
+    /// [`async_io`] reactor and checks again.
+    #[cfg(feature = "async")]
+    pub fn poll_next_owned(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<OwnedFrame>>> {
+        loop {
+            if self.inner.poll() {
+                self.timer = None;
+                return Poll::Ready(Some(self.lease()));
+            }
 
-        Some(Ok(&self.buffer))
+            let poll_interval = self.poll_interval;
+            let timer = self
+                .timer
+                .get_or_insert_with(|| Timer::after(poll_interval));
+            match Pin::new(timer).poll(cx) {
+                Poll::Ready(_) => self.timer = None,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<'a> Stream<'a> for Handle {
+    type Item = Result<Frame<'a>>;
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        while !self.inner.poll() {
+            thread::sleep(self.poll_interval);
+        }
+        Some(self.read())
     }
 }
@@ -7,7 +7,7 @@ use crate::traits::Context as ContextTrait;
 pub struct Context {}
 
 impl<'a> ContextTrait<'a> for Context {
-    type Device = DeviceHandle<'a>;
+    type Device = DeviceHandle;
 
     fn devices(&self) -> Result<Vec<device::Description>> {
         let ctx = match uvc::Context::new() {
@@ -22,6 +22,7 @@ impl<'a> ContextTrait<'a> for Context {
                     let mut description = device::Description {
                         uri: format!("uvc://{}:{}", dev.bus_number(), dev.device_address()),
                         product: "Unknown UVC device".to_string(),
+                        io: Vec::new(),
                     };
 
                     if let Ok(desc) = dev.description() {
@@ -1,5 +1,41 @@
+use std::convert::TryFrom;
+
+use uvc_sys as sys;
+
 use crate::control;
 use crate::error::{Error, ErrorKind, Result};
+use crate::platform::uvc::device::UvcHandle;
+
+fn check(res: sys::uvc_error_t) -> Result<()> {
+    crate::platform::uvc::check(res).map_err(|e| Error::new(ErrorKind::Other, e))
+}
+
+fn int<T: TryFrom<i64>>(state: &control::State) -> Result<T> {
+    let value = match state {
+        control::State::Number(value) => *value as i64,
+        control::State::Integer(value) => *value,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidArgument,
+                "invalid control state",
+            ))
+        }
+    };
+
+    T::try_from(value)
+        .map_err(|_| Error::new(ErrorKind::InvalidArgument, "control value out of range"))
+}
+
+fn index(state: &control::State, len: u32) -> Result<u8> {
+    match state {
+        control::State::Menu(index) if *index < len => Ok(*index as u8),
+        control::State::Menu(_) => Err(Error::new(ErrorKind::InvalidArgument, "invalid menu item")),
+        _ => Err(Error::new(
+            ErrorKind::InvalidArgument,
+            "invalid control state",
+        )),
+    }
+}
 
 pub(crate) enum Control {
     ScanningMode,
@@ -58,76 +94,232 @@ impl Control {
         }
     }
 
-    pub fn get(&self, handle: &uvc::DeviceHandle) -> Result<control::State> {
+    pub fn kind(&self) -> Option<control::Kind> {
         match self {
-            Control::ScanningMode => match handle.scanning_mode() {
-                Ok(mode) => Ok(control::State::String(format!("{:?}", mode))),
-                Err(e) => Err(Error::new(ErrorKind::Other, e)),
-            },
-            Control::AutoExposureMode => match handle.ae_mode() {
-                Ok(mode) => Ok(control::State::String(format!("{:?}", mode))),
-                Err(e) => Err(Error::new(ErrorKind::Other, e)),
-            },
-            Control::AutoExposurePriority => match handle.ae_priority() {
-                Ok(mode) => Ok(control::State::String(format!("{:?}", mode))),
-                Err(e) => Err(Error::new(ErrorKind::Other, e)),
-            },
-            Control::ExposureAbsolute => match handle.exposure_abs() {
-                Ok(val) => Ok(control::State::Number(val as f64)),
-                Err(e) => Err(Error::new(ErrorKind::Other, e)),
-            },
-            Control::ExposureRelative => match handle.exposure_rel() {
-                Ok(val) => Ok(control::State::Number(val as f64)),
-                Err(e) => Err(Error::new(ErrorKind::Other, e)),
-            },
-            Control::FocusAbsolute => match handle.focus_abs() {
-                Ok(val) => Ok(control::State::Number(val as f64)),
-                Err(e) => Err(Error::new(ErrorKind::Other, e)),
-            },
-            Control::FocusRelative => match handle.focus_rel() {
-                Ok((val, _speed)) => Ok(control::State::Number(val as f64)),
-                Err(e) => Err(Error::new(ErrorKind::Other, e)),
-            },
+            Control::AutoExposureMode => Some(control::Kind::AutoExposure),
+            Control::ExposureAbsolute => Some(control::Kind::Exposure),
+            Control::FocusAbsolute => Some(control::Kind::Focus),
+            _ => None,
+        }
+    }
+
+    pub fn scale(&self) -> Option<control::Scale> {
+        match self {
+            // the exposure time is given in units of 100µs
+            Control::ExposureAbsolute => {
+                Some(control::Scale::new(control::Unit::Microseconds, 100.0))
+            }
+            _ => None,
+        }
+    }
+
+    /// Reads the current value
+    pub fn get(&self, handle: &UvcHandle) -> Result<control::State> {
+        self.query(handle, sys::uvc_req_code_UVC_GET_CUR)
+    }
+}
+
+impl Control {
+    /// Returns the default value as reported by the device
+    pub fn default(&self, handle: &UvcHandle) -> Result<control::State> {
+        self.query(handle, sys::uvc_req_code_UVC_GET_DEF)
+    }
+
+    /// Reads one of the values of the control, e.g. the current or the default one
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - Device handle
+    /// * `req` - libuvc request code
+    fn query(&self, handle: &UvcHandle, req: sys::uvc_req_code) -> Result<control::State> {
+        let devh = handle.as_ptr();
+        unsafe {
+            match self {
+                Control::ScanningMode => {
+                    let mut mode = 0;
+                    check(sys::uvc_get_scanning_mode(devh, &mut mode, req))?;
+                    Ok(control::State::Menu(mode as u32))
+                }
+                Control::AutoExposureMode => {
+                    // the mode has a single bit set, its position is the menu index
+                    let mut mode = 0;
+                    check(sys::uvc_get_ae_mode(devh, &mut mode, req))?;
+                    Ok(control::State::Menu(mode.trailing_zeros()))
+                }
+                Control::AutoExposurePriority => {
+                    let mut priority = 0;
+                    check(sys::uvc_get_ae_priority(devh, &mut priority, req))?;
+                    Ok(control::State::Menu(priority as u32))
+                }
+                Control::ExposureAbsolute => {
+                    let mut time = 0;
+                    check(sys::uvc_get_exposure_abs(devh, &mut time, req))?;
+                    Ok(control::State::Number(time as f64))
+                }
+                Control::ExposureRelative => {
+                    let mut step = 0;
+                    check(sys::uvc_get_exposure_rel(devh, &mut step, req))?;
+                    Ok(control::State::Number(step as f64))
+                }
+                Control::FocusAbsolute => {
+                    let mut focus = 0;
+                    check(sys::uvc_get_focus_abs(devh, &mut focus, req))?;
+                    Ok(control::State::Number(focus as f64))
+                }
+                Control::FocusRelative => {
+                    let (mut focus, mut speed) = (0, 0);
+                    check(sys::uvc_get_focus_rel(devh, &mut focus, &mut speed, req))?;
+                    Ok(control::State::Number(focus as f64))
+                }
+            }
+        }
+    }
+
+    /// Writes a new value
+    pub fn set(&self, handle: &UvcHandle, state: &control::State) -> Result<()> {
+        let devh = handle.as_ptr();
+        unsafe {
+            match self {
+                Control::ScanningMode => check(sys::uvc_set_scanning_mode(devh, index(state, 2)?)),
+                Control::AutoExposureMode => {
+                    // the mode has a single bit set, its position is the menu index
+                    check(sys::uvc_set_ae_mode(devh, 1 << index(state, 4)?))
+                }
+                Control::AutoExposurePriority => {
+                    check(sys::uvc_set_ae_priority(devh, index(state, 2)?))
+                }
+                Control::ExposureAbsolute => check(sys::uvc_set_exposure_abs(devh, int(state)?)),
+                Control::ExposureRelative => check(sys::uvc_set_exposure_rel(devh, int(state)?)),
+                Control::FocusAbsolute => check(sys::uvc_set_focus_abs(devh, int(state)?)),
+                Control::FocusRelative => {
+                    // keep the speed the camera is currently configured for, if it is known
+                    let (mut focus, mut speed) = (0, 1);
+                    let req = sys::uvc_req_code_UVC_GET_CUR;
+                    sys::uvc_get_focus_rel(devh, &mut focus, &mut speed, req);
+                    check(sys::uvc_set_focus_rel(devh, int(state)?, speed))
+                }
+            }
+        }
+    }
+
+    /// Returns the descriptor including the default value and limits reported by the device
+    ///
+    /// Numerical controls fall back to the limits of their wire format if the device does not
+    /// report any.
+    pub fn descriptor(&self, handle: &UvcHandle) -> control::Descriptor {
+        let mut desc = control::Descriptor {
+            default: self.default(handle).ok(),
+            ..control::Descriptor::from(self)
+        };
+
+        if let control::Type::Number { range, step } = &mut desc.typ {
+            let number = |req| match self.query(handle, req) {
+                Ok(control::State::Number(val)) => Some(val),
+                _ => None,
+            };
+
+            if let (Some(min), Some(max)) = (
+                number(sys::uvc_req_code_UVC_GET_MIN),
+                number(sys::uvc_req_code_UVC_GET_MAX),
+            ) {
+                *range = (min, max);
+            }
+            match number(sys::uvc_req_code_UVC_GET_RES) {
+                Some(res) if res > 0.0 => *step = res as f32,
+                _ => {}
+            }
+        }
+
+        desc
+    }
+
+    /// Restores the default value as reported by the device
+    pub fn reset(&self, handle: &UvcHandle) -> Result<()> {
+        match self {
+            // relative controls move their absolute counterparts, there is nothing to restore
+            Control::ExposureRelative | Control::FocusRelative => Ok(()),
+            _ => self.set(handle, &self.default(handle)?),
         }
     }
 }
 
+// Numerical ranges cover the whole wire format, see Control::descriptor for the device limits.
 impl From<&Control> for control::Descriptor {
     fn from(ctrl: &Control) -> Self {
         match ctrl {
             Control::ScanningMode => control::Descriptor {
                 id: ctrl.id(),
                 name: String::from(ctrl.name()),
-                flags: control::Flags::READ,
+                flags: control::Flags::READ | control::Flags::WRITE,
+                kind: ctrl.kind(),
+                scale: ctrl.scale(),
+                default: None,
+                value: None,
                 typ: control::Type::Menu(vec![
-                    control::MenuItem::String(String::from("Interlaced")),
-                    control::MenuItem::String(String::from("Progressive")),
+                    control::MenuItem {
+                        index: 0,
+                        value: control::MenuValue::String(String::from("Interlaced")),
+                    },
+                    control::MenuItem {
+                        index: 1,
+                        value: control::MenuValue::String(String::from("Progressive")),
+                    },
                 ]),
             },
             Control::AutoExposureMode => control::Descriptor {
                 id: ctrl.id(),
                 name: String::from(ctrl.name()),
-                flags: control::Flags::READ,
+                flags: control::Flags::READ | control::Flags::WRITE,
+                kind: ctrl.kind(),
+                scale: ctrl.scale(),
+                default: None,
+                value: None,
                 typ: control::Type::Menu(vec![
-                    control::MenuItem::String(String::from("Manual")),
-                    control::MenuItem::String(String::from("Auto")),
-                    control::MenuItem::String(String::from("ShutterPriority")),
-                    control::MenuItem::String(String::from("AperturePriority")),
+                    control::MenuItem {
+                        index: 0,
+                        value: control::MenuValue::String(String::from("Manual")),
+                    },
+                    control::MenuItem {
+                        index: 1,
+                        value: control::MenuValue::String(String::from("Auto")),
+                    },
+                    control::MenuItem {
+                        index: 2,
+                        value: control::MenuValue::String(String::from("ShutterPriority")),
+                    },
+                    control::MenuItem {
+                        index: 3,
+                        value: control::MenuValue::String(String::from("AperturePriority")),
+                    },
                 ]),
             },
             Control::AutoExposurePriority => control::Descriptor {
                 id: ctrl.id(),
                 name: String::from(ctrl.name()),
-                flags: control::Flags::READ,
+                flags: control::Flags::READ | control::Flags::WRITE,
+                kind: ctrl.kind(),
+                scale: ctrl.scale(),
+                default: None,
+                value: None,
                 typ: control::Type::Menu(vec![
-                    control::MenuItem::String(String::from("Constant")),
-                    control::MenuItem::String(String::from("Variable")),
+                    control::MenuItem {
+                        index: 0,
+                        value: control::MenuValue::String(String::from("Constant")),
+                    },
+                    control::MenuItem {
+                        index: 1,
+                        value: control::MenuValue::String(String::from("Variable")),
+                    },
                 ]),
             },
             Control::ExposureAbsolute => control::Descriptor {
                 id: ctrl.id(),
                 name: String::from(ctrl.name()),
-                flags: control::Flags::READ,
+                flags: control::Flags::READ | control::Flags::WRITE,
+                kind: ctrl.kind(),
+                scale: ctrl.scale(),
+                default: None,
+                value: None,
                 typ: control::Type::Number {
                     range: (u32::MIN as f64, u32::MAX as f64),
                     step: 1.0,
@@ -136,7 +328,11 @@ impl From<&Control> for control::Descriptor {
             Control::ExposureRelative => control::Descriptor {
                 id: ctrl.id(),
                 name: String::from(ctrl.name()),
-                flags: control::Flags::READ,
+                flags: control::Flags::READ | control::Flags::WRITE,
+                kind: ctrl.kind(),
+                scale: ctrl.scale(),
+                default: None,
+                value: None,
                 typ: control::Type::Number {
                     range: (i8::MIN as f64, i8::MAX as f64),
                     step: 1.0,
@@ -145,7 +341,11 @@ impl From<&Control> for control::Descriptor {
             Control::FocusAbsolute => control::Descriptor {
                 id: ctrl.id(),
                 name: String::from(ctrl.name()),
-                flags: control::Flags::READ,
+                flags: control::Flags::READ | control::Flags::WRITE,
+                kind: ctrl.kind(),
+                scale: ctrl.scale(),
+                default: None,
+                value: None,
                 typ: control::Type::Number {
                     range: (u16::MIN as f64, u16::MAX as f64),
                     step: 1.0,
@@ -154,7 +354,11 @@ impl From<&Control> for control::Descriptor {
             Control::FocusRelative => control::Descriptor {
                 id: ctrl.id(),
                 name: String::from(ctrl.name()),
-                flags: control::Flags::READ,
+                flags: control::Flags::READ | control::Flags::WRITE,
+                kind: ctrl.kind(),
+                scale: ctrl.scale(),
+                default: None,
+                value: None,
                 typ: control::Type::Number {
                     range: (i8::MIN as f64, i8::MAX as f64),
                     step: 1.0,
@@ -1,18 +1,23 @@
+use std::mem;
+use std::ptr::{self, NonNull};
 use std::sync::Arc;
 
+use uvc_sys as sys;
+
 use crate::control;
 use crate::error::{Error, ErrorKind, Result};
-use crate::format::PixelFormat;
+use crate::format::{ImageFormat, PixelFormat};
+use crate::platform::uvc::check;
 use crate::platform::uvc::control::Control;
 use crate::platform::uvc::stream::Handle as StreamHandle;
 use crate::stream;
 use crate::traits::Device;
 
-pub struct Handle<'a> {
-    inner: Arc<UvcHandle<'a>>,
+pub struct Handle {
+    inner: Arc<UvcHandle>,
 }
 
-impl<'a> Handle<'a> {
+impl Handle {
     pub fn new(bus_number: u8, device_address: u8) -> uvc::Result<Self> {
         let inner = UvcHandle::new(bus_number, device_address)?;
 
@@ -47,37 +52,124 @@ impl<'a> Handle<'a> {
     }
 }
 
-impl<'a> Device<'a> for Handle<'a> {
-    type Stream = StreamHandle<'a>;
+impl Handle {
+    /// Subscribes to control change events
+    ///
+    /// libuvc does not report control changes, so they are detected by polling.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - Control identifiers, all controls are watched if empty
+    pub fn subscribe_controls<'a>(&self, ids: &[u32]) -> Result<control::Poller<'a>> {
+        let inner = self.inner.clone();
+        // the limits do not change, so only the values are polled
+        let controls: Vec<(Control, control::Descriptor)> = Control::all()
+            .into_iter()
+            .map(|ctrl| {
+                let desc = ctrl.descriptor(&inner);
+                (ctrl, desc)
+            })
+            .collect();
+        control::Poller::new(
+            move || {
+                Ok(controls
+                    .iter()
+                    .map(|(ctrl, desc)| control::Descriptor {
+                        value: ctrl.get(&inner).ok(),
+                        ..desc.clone()
+                    })
+                    .collect())
+            },
+            ids,
+        )
+    }
 
-    fn streams(&self) -> Result<Vec<stream::Descriptor>> {
-        let mut streams = Vec::new();
+    /// Returns a stream which produces images
+    ///
+    /// libuvc picks the closest frame rate, the effective interval is reported by the stream.
+    /// Frames are always converted to RGB. If `opts.strict` is set, adjustments are treated as
+    /// error instead.
+    pub fn start_stream_with(
+        &self,
+        desc: &stream::Descriptor,
+        opts: &stream::Options,
+    ) -> Result<StreamHandle> {
+        if opts.io != stream::Io::Auto {
+            return Err(Error::new(
+                ErrorKind::NotSupported,
+                "only the default I/O method is supported",
+            ));
+        }
+
+        // libuvc only deals in whole frames per second, so pick the closest rate
+        let desc_fps = (1.0 / desc.interval.as_f64()).round() as u32;
+        let stream_format = self
+            .inner
+            .formats()
+            .into_iter()
+            .filter(|fmt| fmt.width == desc.width && fmt.height == desc.height)
+            .min_by_key(|fmt| (fmt.fps() as i64 - desc_fps as i64).abs());
+
+        let stream_format = match stream_format {
+            Some(fmt) => fmt,
+            None => return Err(Error::new(ErrorKind::Other, "invalid stream descriptor")),
+        };
+
+        let format = ImageFormat::new(desc.width, desc.height, PixelFormat::Rgb(24));
+        // libuvc matches the fps against the intervals advertised by the device
+        let interval = if stream_format.fps() == desc_fps {
+            desc.interval
+        } else {
+            stream::Fraction::from_fps(stream_format.fps())
+        };
+        if opts.strict && (format.pixfmt != desc.pixfmt || stream_format.fps() != desc_fps) {
+            return Err(Error::new(
+                ErrorKind::NotSupported,
+                format!(
+                    "stream format adjusted to {}x{} {} @ {}s",
+                    format.width, format.height, format.pixfmt, interval
+                ),
+            ));
+        }
+
+        let mut ctrl: sys::uvc_stream_ctrl_t = unsafe { mem::zeroed() };
+        let res = unsafe {
+            sys::uvc_get_stream_ctrl_format_size(
+                self.inner.as_ptr(),
+                &mut ctrl,
+                stream_format.format,
+                stream_format.width as i32,
+                stream_format.height as i32,
+                stream_format.fps() as i32,
+            )
+        };
+        if let Err(e) = check(res) {
+            return Err(Error::new(ErrorKind::Other, e));
+        }
+
+        match StreamHandle::new(self.inner.clone(), ctrl, format, interval, opts) {
+            Ok(handle) => Ok(handle),
+            Err(e) => Err(Error::new(ErrorKind::Other, e)),
+        }
+    }
+}
+
+impl<'a> Device<'a> for Handle {
+    type Stream = StreamHandle;
 
-        self.inner
-            .handle
-            .supported_formats()
+    fn streams(&self) -> Result<Vec<stream::Descriptor>> {
+        // intervals are given in units of 100ns
+        let streams = self
+            .inner
+            .formats()
             .into_iter()
-            .for_each(|fmt_desc| {
-                fmt_desc
-                    .supported_formats()
-                    .into_iter()
-                    .for_each(|frame_desc| {
-                        let pixfmt = match frame_desc.subtype() {
-                            uvc::DescriptionSubtype::FormatMJPEG
-                            | uvc::DescriptionSubtype::FrameMJPEG => PixelFormat::Jpeg,
-                            _ => PixelFormat::Rgb(24),
-                        };
-
-                        for interval in frame_desc.intervals_duration() {
-                            streams.push(stream::Descriptor {
-                                width: frame_desc.width() as u32,
-                                height: frame_desc.height() as u32,
-                                pixfmt: pixfmt.clone(),
-                                interval,
-                            });
-                        }
-                    });
-            });
+            .map(|fmt| stream::Descriptor {
+                width: fmt.width,
+                height: fmt.height,
+                pixfmt: fmt.pixfmt,
+                interval: stream::Fraction::new(fmt.interval, 10_000_000).reduce(),
+            })
+            .collect();
 
         Ok(streams)
     }
@@ -85,90 +177,197 @@ impl<'a> Device<'a> for Handle<'a> {
     fn controls(&self) -> Result<Vec<control::Descriptor>> {
         let controls = Control::all()
             .into_iter()
-            .map(|ctrl| <control::Descriptor>::from(&ctrl))
+            .map(|ctrl| ctrl.descriptor(&self.inner))
             .collect();
         Ok(controls)
     }
 
+    fn control_descriptor(&self, id: u32) -> Result<control::Descriptor> {
+        match Control::from_id(id) {
+            Some(ctrl) => Ok(ctrl.descriptor(&self.inner)),
+            None => Err(Error::new(ErrorKind::NotFound, "unknown control ID")),
+        }
+    }
+
     fn control(&self, id: u32) -> Result<control::State> {
         match Control::from_id(id) {
-            Some(ctrl) => ctrl.get(&self.inner.handle),
-            None => Err(Error::new(ErrorKind::Other, "unknown control ID")),
+            Some(ctrl) => ctrl.get(&self.inner),
+            None => Err(Error::new(ErrorKind::NotFound, "unknown control ID")),
         }
     }
 
-    fn set_control(&mut self, _id: u32, _val: &control::State) -> Result<()> {
-        Err(Error::from(ErrorKind::NotSupported))
+    fn set_control(&mut self, id: u32, val: &control::State) -> Result<()> {
+        match Control::from_id(id) {
+            Some(ctrl) => ctrl.set(&self.inner, val),
+            None => Err(Error::new(ErrorKind::NotFound, "unknown control ID")),
+        }
     }
 
-    fn start_stream(&self, desc: &stream::Descriptor) -> Result<Self::Stream> {
-        let dev_handle = self.inner.clone();
-        let dev_handle_ptr = &*dev_handle.handle as *const uvc::DeviceHandle;
-        let dev_handle_ref = unsafe { &*dev_handle_ptr as &uvc::DeviceHandle };
-
-        let desc_fps = (1.0 / desc.interval.as_secs_f64()) as u64;
-        let stream_format = self.inner.handle.get_preferred_format(|x, y| {
-            if x.width == desc.width && x.height == desc.height && x.fps as u64 >= desc_fps {
-                x
-            } else if x.width == desc.width && x.height == desc.height {
-                x
-            } else {
-                y
+    fn reset_controls(&mut self) -> Result<()> {
+        let mut res = Ok(());
+        for ctrl in Control::all() {
+            if let Err(e) = ctrl.reset(&self.inner) {
+                if res.is_ok() {
+                    res = Err(e);
+                }
             }
-        });
+        }
 
-        let stream_format = match stream_format {
-            Some(fmt) => {
-                if fmt.width != desc.width || fmt.height != desc.height {
-                    return Err(Error::new(ErrorKind::Other, "invalid stream descriptor"));
+        res
+    }
+
+    fn start_stream(&self, desc: &stream::Descriptor) -> Result<Self::Stream> {
+        self.start_stream_with(desc, &stream::Options::default())
+    }
+}
+
+/// libuvc context, device and handle
+///
+/// The device is opened through uvc-sys directly so we own the raw handle, which is needed for
+/// the control requests the uvc crate does not wrap.
+pub struct UvcHandle {
+    ctx: NonNull<sys::uvc_context>,
+    dev: NonNull<sys::uvc_device>,
+    devh: NonNull<sys::uvc_device_handle>,
+}
+
+// libuvc handles may be used from any thread, the streaming thread is managed by libuvc itself
+unsafe impl Send for UvcHandle {}
+unsafe impl Sync for UvcHandle {}
+
+impl UvcHandle {
+    pub fn new(bus_number: u8, device_address: u8) -> uvc::Result<Self> {
+        unsafe {
+            let mut ctx = ptr::null_mut();
+            check(sys::uvc_init(&mut ctx, ptr::null_mut()))?;
+            let ctx = NonNull::new(ctx).ok_or(uvc::Error::Other)?;
+
+            let dev = match Self::find(ctx.as_ptr(), bus_number, device_address) {
+                Ok(dev) => dev,
+                Err(e) => {
+                    sys::uvc_exit(ctx.as_ptr());
+                    return Err(e);
                 }
+            };
 
-                fmt
+            let mut devh = ptr::null_mut();
+            let res = check(sys::uvc_open(dev.as_ptr(), &mut devh));
+            match res.and_then(|_| NonNull::new(devh).ok_or(uvc::Error::Other)) {
+                Ok(devh) => Ok(UvcHandle { ctx, dev, devh }),
+                Err(e) => {
+                    sys::uvc_unref_device(dev.as_ptr());
+                    sys::uvc_exit(ctx.as_ptr());
+                    Err(e)
+                }
             }
-            None => return Err(Error::new(ErrorKind::Other, "failed to query formats")),
-        };
+        }
+    }
 
-        let stream_handle = match dev_handle_ref.get_stream_handle_with_format(stream_format) {
-            Ok(handle) => handle,
-            Err(e) => return Err(Error::new(ErrorKind::Other, e)),
-        };
+    /// Looks up a device by its USB address and takes a reference to it
+    unsafe fn find(
+        ctx: *mut sys::uvc_context,
+        bus_number: u8,
+        device_address: u8,
+    ) -> uvc::Result<NonNull<sys::uvc_device>> {
+        let mut list = ptr::null_mut();
+        check(sys::uvc_get_device_list(ctx, &mut list))?;
 
-        match StreamHandle::new(dev_handle, stream_handle) {
-            Ok(handle) => Ok(handle),
-            Err(e) => Err(Error::new(ErrorKind::Other, e)),
+        let mut dev = None;
+        let mut iter = list;
+        while !(*iter).is_null() {
+            if sys::uvc_get_bus_number(*iter) == bus_number
+                && sys::uvc_get_device_address(*iter) == device_address
+            {
+                // keep the device alive once the list is freed
+                sys::uvc_ref_device(*iter);
+                dev = NonNull::new(*iter);
+                break;
+            }
+            iter = iter.add(1);
         }
+
+        sys::uvc_free_device_list(list, 1);
+        dev.ok_or(uvc::Error::NotFound)
     }
-}
 
-pub struct UvcHandle<'a> {
-    handle: Box<uvc::DeviceHandle<'a>>,
-    _dev: Box<uvc::Device<'a>>,
-    _ctx: Box<uvc::Context<'a>>,
+    /// Returns the raw libuvc handle
+    pub fn as_ptr(&self) -> *mut sys::uvc_device_handle {
+        self.devh.as_ptr()
+    }
+
+    /// Returns all frame sizes and intervals advertised by the device
+    fn formats(&self) -> Vec<Format> {
+        let mut formats = Vec::new();
+
+        unsafe {
+            let mut format_desc = sys::uvc_get_format_descs(self.as_ptr());
+            while !format_desc.is_null() {
+                let subtype = (*format_desc).bDescriptorSubtype;
+                let (format, pixfmt) = if subtype == sys::uvc_vs_desc_subtype_UVC_VS_FORMAT_MJPEG {
+                    (
+                        sys::uvc_frame_format_UVC_FRAME_FORMAT_MJPEG,
+                        PixelFormat::Jpeg,
+                    )
+                } else if subtype == sys::uvc_vs_desc_subtype_UVC_VS_FORMAT_UNCOMPRESSED {
+                    (
+                        sys::uvc_frame_format_UVC_FRAME_FORMAT_UNCOMPRESSED,
+                        PixelFormat::Rgb(24),
+                    )
+                } else {
+                    (
+                        sys::uvc_frame_format_UVC_FRAME_FORMAT_ANY,
+                        PixelFormat::Rgb(24),
+                    )
+                };
+
+                let mut frame_desc = (*format_desc).frame_descs;
+                while !frame_desc.is_null() {
+                    // the list of intervals is terminated by zero
+                    let mut interval = (*frame_desc).intervals;
+                    while !interval.is_null() && *interval != 0 {
+                        formats.push(Format {
+                            format,
+                            pixfmt: pixfmt.clone(),
+                            width: (*frame_desc).wWidth as u32,
+                            height: (*frame_desc).wHeight as u32,
+                            interval: *interval,
+                        });
+                        interval = interval.add(1);
+                    }
+
+                    frame_desc = (*frame_desc).next;
+                }
+
+                format_desc = (*format_desc).next;
+            }
+        }
+
+        formats
+    }
 }
 
-impl<'a> UvcHandle<'a> {
-    pub fn new(bus_number: u8, device_address: u8) -> uvc::Result<Self> {
-        let ctx = Box::new(uvc::Context::new()?);
-        let ctx_ptr = &*ctx as *const uvc::Context;
-        let ctx_ref = unsafe { &*ctx_ptr as &uvc::Context };
-
-        let dev =
-            if let Some(dev) = ctx_ref.devices()?.into_iter().find(|dev| {
-                dev.bus_number() == bus_number && dev.device_address() == device_address
-            }) {
-                Box::new(dev)
-            } else {
-                return Err(uvc::Error::NotFound);
-            };
-        let dev_ptr = &*dev as *const uvc::Device;
-        let dev_ref = unsafe { &*dev_ptr as &uvc::Device };
+impl Drop for UvcHandle {
+    fn drop(&mut self) {
+        unsafe {
+            sys::uvc_close(self.devh.as_ptr());
+            sys::uvc_unref_device(self.dev.as_ptr());
+            sys::uvc_exit(self.ctx.as_ptr());
+        }
+    }
+}
 
-        let handle = Box::new(dev_ref.open()?);
+/// Frame size and interval advertised by the device
+struct Format {
+    format: sys::uvc_frame_format,
+    pixfmt: PixelFormat,
+    width: u32,
+    height: u32,
+    // in units of 100ns
+    interval: u32,
+}
 
-        Ok(UvcHandle {
-            handle,
-            _dev: dev,
-            _ctx: ctx,
-        })
+impl Format {
+    fn fps(&self) -> u32 {
+        10_000_000 / self.interval
     }
 }
@@ -9,3 +9,13 @@ pub mod device;
 pub mod stream;
 
 pub use context::Context;
+
+use uvc_sys as sys;
+
+/// Converts a status code returned by libuvc.
+pub(crate) fn check(res: sys::uvc_error_t) -> uvc::Result<()> {
+    match uvc::Error::from(res) {
+        uvc::Error::Success => Ok(()),
+        e => Err(e),
+    }
+}
@@ -1,63 +1,271 @@
-use std::sync::{mpsc, Arc};
+use std::os::raw::c_void;
+use std::slice;
+use std::sync::{mpsc, Arc, Mutex};
+use std::task::Waker;
+#[cfg(feature = "async")]
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
-use crate::error::Result;
+use uvc_sys as sys;
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::format::ImageFormat;
+use crate::platform::uvc::check;
 use crate::platform::uvc::device::UvcHandle;
+use crate::stream::{Fraction, Frame, Metadata, Options, OwnedFrame, Policy, Pool};
 use crate::traits::Stream;
 
-pub struct Handle<'a> {
-    rx: mpsc::Receiver<uvc::Result<uvc::Frame>>,
+type Item = (uvc::Result<Image>, Instant);
+
+/// RGB image converted from a libuvc frame
+struct Image {
+    data: Vec<u8>,
+    sequence: u32,
+}
+
+/// Sending end of the frame callback
+pub struct Sender {
+    tx: mpsc::SyncSender<Item>,
+    // task waiting for the next frame, if any
+    waker: Arc<Mutex<Option<Waker>>>,
+}
+
+/// Converts a frame to RGB, libuvc reuses the input frame once the callback returns
+unsafe fn convert(frame: *mut sys::uvc_frame) -> uvc::Result<Image> {
+    let rgb = sys::uvc_allocate_frame(((*frame).width * (*frame).height * 3) as usize);
+    if rgb.is_null() {
+        return Err(uvc::Error::NoMem);
+    }
+
+    let res = check(sys::uvc_any2rgb(frame, rgb)).map(|_| Image {
+        data: slice::from_raw_parts((*rgb).data as *const u8, (*rgb).data_bytes).to_vec(),
+        sequence: (*frame).sequence,
+    });
+    sys::uvc_free_frame(rgb);
+    res
+}
+
+/// Frame callback invoked by the libuvc streaming thread
+unsafe extern "C" fn callback(frame: *mut sys::uvc_frame, user_ptr: *mut c_void) {
+    let sender = &*(user_ptr as *const Sender);
+
+    // libuvc does not expose the capture time, so record the arrival time instead
+    match sender.tx.send((convert(frame), Instant::now())) {
+        Ok(()) => {
+            if let Some(waker) = sender.waker.lock().ok().and_then(|mut waker| waker.take()) {
+                waker.wake();
+            }
+        }
+        Err(_) => {
+            // The receiving end hung up.
+            // This should only ever happen once (when self.rx is dropped).
+        }
+    }
+}
 
-    // these are required to keep the frame callback alive
-    _stream: uvc::ActiveStream<'a, mpsc::SyncSender<uvc::Result<uvc::Frame>>>,
-    _stream_handle: uvc::StreamHandle<'a>,
-    _dev_handle: Arc<UvcHandle<'a>>,
+/// Running libuvc stream, stopped when dropped
+struct Active {
+    dev_handle: Arc<UvcHandle>,
+    sender: *mut Sender,
 }
 
-impl<'a> Handle<'a> {
+// the sender is only touched by the streaming thread until the stream is stopped
+unsafe impl Send for Active {}
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        unsafe {
+            // joins the streaming thread, so the callback is done with the sender afterwards
+            sys::uvc_stop_streaming(self.dev_handle.as_ptr());
+            drop(Box::from_raw(self.sender));
+        }
+    }
+}
+
+pub struct Handle {
+    rx: mpsc::Receiver<Item>,
+    #[cfg_attr(not(feature = "async"), allow(dead_code))]
+    waker: Arc<Mutex<Option<Waker>>>,
+    frame: Option<Image>,
+    started: Instant,
+    pool: Pool,
+    buffers: usize,
+    policy: Policy,
+    format: ImageFormat,
+    interval: Fraction,
+
+    // stops the stream, must be dropped after the receiver so a blocked callback can return
+    _stream: Active,
+}
+
+// number of queued frames and pooled buffers unless requested otherwise
+const BUFFERS: usize = 4;
+
+impl Handle {
     pub fn new(
-        dev_handle: Arc<UvcHandle<'a>>,
-        mut stream_handle: uvc::StreamHandle<'a>,
+        dev_handle: Arc<UvcHandle>,
+        mut ctrl: sys::uvc_stream_ctrl_t,
+        format: ImageFormat,
+        interval: Fraction,
+        opts: &Options,
     ) -> uvc::Result<Self> {
-        let stream_handle_ptr = &mut stream_handle as *mut uvc::StreamHandle;
-        let stream_handle_ref = unsafe { &mut *stream_handle_ptr as &mut uvc::StreamHandle };
-
-        // establish a rendezvous channel
-        let (tx, rx) = mpsc::sync_channel(0);
-        let stream = stream_handle_ref.start_stream(
-            |frame, tx| {
-                match tx.send(frame.to_rgb()) {
-                    Ok(()) => {}
-                    Err(_) => {
-                        // The receiving end hung up.
-                        // This should only ever happen once (when self.rx is dropped).
-                    }
-                }
-            },
+        let buffers = opts.buffers.map_or(BUFFERS, |count| count as usize);
+
+        // Establish a channel which holds as many frames as there are pooled buffers. The
+        // callback blocks while the channel is full, then wakes up any task waiting for a frame.
+        let (tx, rx) = mpsc::sync_channel(buffers);
+        let waker = Arc::new(Mutex::new(None));
+        let sender = Box::into_raw(Box::new(Sender {
             tx,
-        )?;
+            waker: waker.clone(),
+        }));
+
+        let res = unsafe {
+            sys::uvc_start_streaming(
+                dev_handle.as_ptr(),
+                &mut ctrl,
+                Some(callback),
+                sender as *mut c_void,
+                0,
+            )
+        };
+        if let Err(e) = check(res) {
+            drop(unsafe { Box::from_raw(sender) });
+            return Err(e);
+        }
 
         Ok(Handle {
             rx,
-            _stream: stream,
-            _stream_handle: stream_handle,
-            _dev_handle: dev_handle,
+            waker,
+            frame: None,
+            started: Instant::now(),
+            pool: Pool::new(buffers),
+            buffers,
+            policy: opts.policy,
+            format,
+            interval,
+            _stream: Active { dev_handle, sender },
         })
     }
-}
 
-impl<'a, 'b> Stream<'b> for Handle<'a> {
-    type Item = Result<&'b [u8]>;
+    /// Returns the image format of the frames
+    pub fn format(&self) -> &ImageFormat {
+        &self.format
+    }
 
-    fn next(&'b mut self) -> Option<Self::Item> {
-        let frame = self.rx.recv().unwrap();
-        let pixels = match &frame {
-            Ok(frame) => frame.to_bytes(),
+    /// Returns the frame interval negotiated with the device
+    pub fn interval(&self) -> Fraction {
+        self.interval
+    }
+
+    /// Returns the number of frames queued and buffers available for owned frames
+    pub fn buffers(&self) -> usize {
+        self.buffers
+    }
+
+    /// Skips to the most recent frame if old frames are to be dropped
+    fn latest(&self, mut item: Item) -> Item {
+        if self.policy == Policy::DropOld {
+            while let Ok(newer) = self.rx.try_recv() {
+                item = newer;
+            }
+        }
+
+        item
+    }
+
+    fn frame(&mut self, item: Item) -> Option<Result<Frame<'_>>> {
+        let (frame, arrival) = self.latest(item);
+        let frame = match frame {
+            Ok(frame) => self.frame.insert(frame),
             Err(_) => {
                 // The format conversion failed. Pretend the stream died.
                 return None;
             }
         };
 
-        Some(Ok(&pixels))
+        let pixels = &frame.data[..];
+        let meta = Metadata {
+            timestamp: arrival.saturating_duration_since(self.started),
+            sequence: frame.sequence,
+            bytesused: pixels.len(),
+            corrupted: false,
+            buffer: None,
+        };
+        Some(Ok(Frame::new(pixels).meta(meta)))
+    }
+
+    fn lease(&mut self, item: Item) -> Option<Result<OwnedFrame>> {
+        let pool = self.pool.clone();
+        match self.frame(item)? {
+            Ok(frame) => Some(pool.lease(&frame)),
+            Err(e) => Some(Err(e)),
+        }
+    }
+
+    /// Advances the stream, waiting at most `timeout` for the next frame
+    ///
+    /// Returns an error of kind [`ErrorKind::Timeout`] if no frame arrived in time.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<Result<Frame<'_>>> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(item) => self.frame(item),
+            Err(mpsc::RecvTimeoutError::Timeout) => Some(Err(Error::new(
+                ErrorKind::Timeout,
+                "timed out waiting for a frame",
+            ))),
+            Err(mpsc::RecvTimeoutError::Disconnected) => None,
+        }
+    }
+
+    /// Returns the next frame if one is ready, without waiting
+    ///
+    /// Returns an error of kind [`ErrorKind::Timeout`] if no frame is ready.
+    pub fn try_next(&mut self) -> Option<Result<Frame<'_>>> {
+        match self.rx.try_recv() {
+            Ok(item) => self.frame(item),
+            Err(mpsc::TryRecvError::Empty) => {
+                Some(Err(Error::new(ErrorKind::Timeout, "no frame available")))
+            }
+            Err(mpsc::TryRecvError::Disconnected) => None,
+        }
+    }
+
+    /// Advances the stream and returns the next frame as an owned buffer
+    ///
+    /// libuvc frames are copied into a fixed pool of buffers.
+    pub fn next_owned(&mut self) -> Option<Result<OwnedFrame>> {
+        let item = self.rx.recv().ok()?;
+        self.lease(item)
+    }
+
+    /// Attempts to pull out the next frame, registering the current task for wakeup if no frame
+    /// is available yet
+    ///
+    /// The task is woken up by the libuvc frame callback.
+    #[cfg(feature = "async")]
+    pub fn poll_next_owned(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<OwnedFrame>>> {
+        match self.rx.try_recv() {
+            Ok(item) => return Poll::Ready(self.lease(item)),
+            Err(mpsc::TryRecvError::Empty) => {}
+            Err(mpsc::TryRecvError::Disconnected) => return Poll::Ready(None),
+        }
+
+        *self.waker.lock().unwrap() = Some(cx.waker().clone());
+
+        // check again in case a frame arrived before the waker was registered
+        match self.rx.try_recv() {
+            Ok(item) => Poll::Ready(self.lease(item)),
+            Err(mpsc::TryRecvError::Empty) => Poll::Pending,
+            Err(mpsc::TryRecvError::Disconnected) => Poll::Ready(None),
+        }
+    }
+}
+
+impl<'a> Stream<'a> for Handle {
+    type Item = Result<Frame<'a>>;
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        let item = self.rx.recv().ok()?;
+        self.frame(item)
     }
 }
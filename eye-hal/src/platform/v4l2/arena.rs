@@ -0,0 +1,782 @@
+use std::alloc::{self, Layout};
+use std::convert::TryFrom;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+#[cfg(feature = "async")]
+use std::task::Waker;
+use std::time::{Duration, Instant};
+use std::{error, fmt, io, mem, ptr, slice};
+
+use v4l::buffer::{Flags as BufFlags, Type as BufType};
+use v4l::device::Handle as DeviceHandle;
+use v4l::v4l2;
+use v4l::v4l_sys::*;
+use v4l::{Memory, Timestamp};
+
+use crate::stream::{Io, Metadata};
+
+/// Page aligned user space memory
+///
+/// Used as backing store for user pointer streaming if the caller did not supply any buffers.
+pub struct Aligned {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+// We own the allocation exclusively.
+unsafe impl Send for Aligned {}
+
+impl Aligned {
+    /// Allocates zeroed memory, aligned to the page size
+    ///
+    /// # Arguments
+    ///
+    /// * `len` - Number of bytes
+    pub fn new(len: usize) -> io::Result<Self> {
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let layout = Layout::from_size_align(usize::max(len, 1), page)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        if ptr.is_null() {
+            return Err(io::Error::new(
+                io::ErrorKind::OutOfMemory,
+                "failed to allocate buffer",
+            ));
+        }
+
+        Ok(Aligned { ptr, layout })
+    }
+}
+
+impl AsMut<[u8]> for Aligned {
+    fn as_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.layout.size()) }
+    }
+}
+
+impl Drop for Aligned {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr, self.layout) }
+    }
+}
+
+/// What keeps the memory of a buffer alive
+enum Backing {
+    /// Driver memory, mapped into our address space
+    Mapped,
+    /// User space memory, owned by us
+    User(#[allow(dead_code)] Box<dyn AsMut<[u8]> + Send>),
+    /// Imported DMA buffer, mapped into our address space for reading
+    DmaBuf(OwnedFd),
+    /// Memory planes of a multi-planar driver buffer, mapped into our address space
+    ///
+    /// Frames spanning several memory planes are gathered in a staging buffer, so they can be
+    /// handed out as one contiguous slice.
+    Planes(Vec<(*mut u8, usize)>, #[allow(dead_code)] Option<Aligned>),
+}
+
+struct Buffer {
+    ptr: *mut u8,
+    len: usize,
+    backing: Backing,
+    // offset and length of each plane in the frame data, updated when the buffer is dequeued
+    layout: Mutex<Vec<(usize, usize)>>,
+}
+
+impl Buffer {
+    fn new(ptr: *mut u8, len: usize, backing: Backing) -> Self {
+        Buffer {
+            ptr,
+            len,
+            backing,
+            layout: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns a buffer made up of mapped memory planes
+    fn planes(maps: Vec<(*mut u8, usize)>) -> io::Result<Self> {
+        if let [(ptr, len)] = maps[..] {
+            return Ok(Buffer::new(ptr, len, Backing::Planes(maps, None)));
+        }
+
+        let len = maps.iter().map(|map| map.1).sum();
+        let staging = Aligned::new(len)?;
+        Ok(Buffer::new(
+            staging.ptr,
+            len,
+            Backing::Planes(maps, Some(staging)),
+        ))
+    }
+}
+
+/// Every buffer is lent out, none can be dequeued until one is handed back
+///
+/// Carried as payload of the [`io::Error`] returned by [`Arena::dequeue`] and
+/// [`Arena::try_dequeue`], see [`exhausted`].
+#[derive(Debug)]
+pub struct Exhausted;
+
+impl fmt::Display for Exhausted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "all buffers are in use")
+    }
+}
+
+impl error::Error for Exhausted {}
+
+/// Returns whether an error was caused by all buffers being lent out
+///
+/// # Arguments
+///
+/// * `err` - Error returned by the arena
+#[cfg(feature = "async")]
+pub fn exhausted(err: &io::Error) -> bool {
+    matches!(err.get_ref(), Some(e) if e.is::<Exhausted>())
+}
+
+/// Stream buffers shared with the driver
+///
+/// The arena is shared between a stream and the owned frames it handed out, so the memory stays
+/// alive for as long as anyone may still read from it.
+pub struct Arena {
+    handle: Arc<DeviceHandle>,
+    buf_type: BufType,
+    io: Io,
+    mplane: bool,
+    bufs: Vec<Buffer>,
+    queued: AtomicUsize,
+    // buffers waiting to be filled by read(), unused by the streaming methods
+    free: Mutex<Vec<usize>>,
+    sequence: AtomicU32,
+    // signalled whenever a buffer is handed back to the driver
+    returned: Condvar,
+    returned_lock: Mutex<()>,
+    // task waiting for a buffer to be handed back, if any
+    #[cfg(feature = "async")]
+    waker: Mutex<Option<Waker>>,
+}
+
+// The buffers are only ever read through shared references while the driver does not own them,
+// i.e. between dequeuing and queuing them again.
+unsafe impl Send for Arena {}
+unsafe impl Sync for Arena {}
+
+impl Arena {
+    /// Requests and maps driver buffers
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - Device handle
+    /// * `buf_type` - Buffer type
+    /// * `count` - Number of buffers to request, the driver may grant a different amount
+    pub fn new(handle: Arc<DeviceHandle>, buf_type: BufType, count: u32) -> io::Result<Self> {
+        let mut arena = Arena::empty(handle, buf_type, Io::Mmap);
+
+        for index in 0..arena.request(count)? {
+            let mut v4l2_planes: [v4l2_plane; VIDEO_MAX_PLANES as usize] = unsafe { mem::zeroed() };
+            let mut v4l2_buf = v4l2_buffer {
+                index,
+                ..arena.buffer_desc(&mut v4l2_planes)
+            };
+            unsafe {
+                v4l2::ioctl(
+                    arena.handle.fd(),
+                    v4l2::vidioc::VIDIOC_QUERYBUF,
+                    &mut v4l2_buf as *mut _ as *mut std::os::raw::c_void,
+                )?;
+            }
+
+            if arena.mplane {
+                let mut maps = Vec::new();
+                for plane in &v4l2_planes[..v4l2_buf.length as usize] {
+                    let ptr = unsafe {
+                        v4l2::mmap(
+                            ptr::null_mut(),
+                            plane.length as usize,
+                            libc::PROT_READ | libc::PROT_WRITE,
+                            libc::MAP_SHARED,
+                            arena.handle.fd(),
+                            plane.m.mem_offset as libc::off_t,
+                        )
+                    };
+                    match ptr {
+                        Ok(ptr) => maps.push((ptr as *mut u8, plane.length as usize)),
+                        Err(e) => {
+                            for (ptr, len) in maps {
+                                let _ = unsafe { v4l2::munmap(ptr as *mut _, len) };
+                            }
+                            return Err(e);
+                        }
+                    }
+                }
+                arena.bufs.push(Buffer::planes(maps)?);
+                continue;
+            }
+
+            unsafe {
+                let ptr = v4l2::mmap(
+                    ptr::null_mut(),
+                    v4l2_buf.length as usize,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_SHARED,
+                    arena.handle.fd(),
+                    v4l2_buf.m.offset as libc::off_t,
+                )?;
+                arena.bufs.push(Buffer::new(
+                    ptr as *mut u8,
+                    v4l2_buf.length as usize,
+                    Backing::Mapped,
+                ));
+            }
+        }
+
+        Ok(arena)
+    }
+
+    /// Sets up streaming into user space memory
+    ///
+    /// Drivers which grant fewer buffers than supplied only get to use the first ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - Device handle
+    /// * `buf_type` - Buffer type
+    /// * `bufs` - User space buffers
+    pub fn with_userptr(
+        handle: Arc<DeviceHandle>,
+        buf_type: BufType,
+        bufs: Vec<Box<dyn AsMut<[u8]> + Send>>,
+    ) -> io::Result<Self> {
+        let mut arena = Arena::empty(handle, buf_type, Io::UserPtr);
+        // supplied buffers are queued as a single plane
+        if arena.mplane {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "user space buffers are not supported for multi-planar streams",
+            ));
+        }
+
+        let count = arena.request(bufs.len() as u32)? as usize;
+        for mut buf in bufs.into_iter().take(count) {
+            let mem = AsMut::<[u8]>::as_mut(&mut *buf);
+            arena
+                .bufs
+                .push(Buffer::new(mem.as_mut_ptr(), mem.len(), Backing::User(buf)));
+        }
+
+        Ok(arena)
+    }
+
+    /// Sets up streaming into DMA buffers
+    ///
+    /// The buffers are mapped into our address space so frames can be read as usual. Drivers
+    /// which grant fewer buffers than supplied only get to use the first ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - Device handle
+    /// * `buf_type` - Buffer type
+    /// * `fds` - DMABUF file descriptors
+    pub fn with_dmabuf(
+        handle: Arc<DeviceHandle>,
+        buf_type: BufType,
+        fds: Vec<OwnedFd>,
+    ) -> io::Result<Self> {
+        let mut arena = Arena::empty(handle, buf_type, Io::DmaBuf);
+        // supplied buffers are queued as a single plane
+        if arena.mplane {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "DMA buffers are not supported for multi-planar streams",
+            ));
+        }
+
+        let count = arena.request(fds.len() as u32)? as usize;
+        for fd in fds.into_iter().take(count) {
+            // DMA buffers report their size when seeking to the end
+            let len = unsafe { libc::lseek(fd.as_raw_fd(), 0, libc::SEEK_END) };
+            if len < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let ptr = unsafe {
+                v4l2::mmap(
+                    ptr::null_mut(),
+                    len as usize,
+                    libc::PROT_READ,
+                    libc::MAP_SHARED,
+                    fd.as_raw_fd(),
+                    0,
+                )?
+            };
+            arena.bufs.push(Buffer::new(
+                ptr as *mut u8,
+                len as usize,
+                Backing::DmaBuf(fd),
+            ));
+        }
+
+        Ok(arena)
+    }
+
+    /// Sets up capturing with read() calls into buffers allocated by us
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - Device handle
+    /// * `size` - Size of a frame in bytes
+    /// * `count` - Number of buffers
+    pub fn with_read(handle: Arc<DeviceHandle>, size: usize, count: u32) -> io::Result<Self> {
+        let mut arena = Arena::empty(handle, BufType::VideoCapture, Io::Read);
+
+        for _ in 0..count {
+            let mut buf = Aligned::new(size)?;
+            arena.bufs.push(Buffer::new(
+                buf.as_mut().as_mut_ptr(),
+                size,
+                Backing::User(Box::new(buf)),
+            ));
+        }
+
+        Ok(arena)
+    }
+
+    fn empty(handle: Arc<DeviceHandle>, buf_type: BufType, io: Io) -> Self {
+        Arena {
+            handle,
+            buf_type,
+            io,
+            mplane: buf_type as u32 == BufType::VideoCaptureMplane as u32,
+            bufs: Vec::new(),
+            queued: AtomicUsize::new(0),
+            free: Mutex::new(Vec::new()),
+            sequence: AtomicU32::new(0),
+            returned: Condvar::new(),
+            returned_lock: Mutex::new(()),
+            #[cfg(feature = "async")]
+            waker: Mutex::new(None),
+        }
+    }
+
+    /// Returns the V4L2 memory type of the buffers
+    fn memory(&self) -> u32 {
+        match self.io {
+            Io::UserPtr => Memory::UserPtr as u32,
+            Io::DmaBuf => Memory::DmaBuf as u32,
+            _ => Memory::Mmap as u32,
+        }
+    }
+
+    /// Returns the I/O method used to exchange buffers with the driver
+    pub fn io(&self) -> Io {
+        self.io
+    }
+
+    /// Requests buffers from the driver, returns the number of buffers granted
+    fn request(&self, count: u32) -> io::Result<u32> {
+        let mut v4l2_reqbufs = v4l2_requestbuffers {
+            count,
+            type_: self.buf_type as u32,
+            memory: self.memory(),
+            ..unsafe { mem::zeroed() }
+        };
+        unsafe {
+            v4l2::ioctl(
+                self.handle.fd(),
+                v4l2::vidioc::VIDIOC_REQBUFS,
+                &mut v4l2_reqbufs as *mut _ as *mut std::os::raw::c_void,
+            )?;
+        }
+
+        Ok(v4l2_reqbufs.count)
+    }
+
+    /// Exports the driver buffers as DMABUF file descriptors
+    ///
+    /// Only buffers allocated by the driver can be exported.
+    pub fn export(&self) -> io::Result<Vec<OwnedFd>> {
+        if self.io != Io::Mmap {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "only driver buffers can be exported",
+            ));
+        }
+
+        let mut fds = Vec::with_capacity(self.bufs.len());
+        for (index, buf) in self.bufs.iter().enumerate() {
+            if let Backing::Planes(maps, _) = &buf.backing {
+                if maps.len() > 1 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "buffers with several memory planes cannot be exported",
+                    ));
+                }
+            }
+
+            let mut v4l2_expbuf = v4l2_exportbuffer {
+                type_: self.buf_type as u32,
+                index: index as u32,
+                flags: libc::O_CLOEXEC as u32,
+                ..unsafe { mem::zeroed() }
+            };
+            unsafe {
+                v4l2::ioctl(
+                    self.handle.fd(),
+                    v4l2::vidioc::VIDIOC_EXPBUF,
+                    &mut v4l2_expbuf as *mut _ as *mut std::os::raw::c_void,
+                )?;
+                fds.push(OwnedFd::from_raw_fd(v4l2_expbuf.fd));
+            }
+        }
+
+        Ok(fds)
+    }
+
+    /// Returns a buffer description, pointing to `planes` for multi-planar buffers
+    fn buffer_desc(&self, planes: &mut [v4l2_plane; VIDEO_MAX_PLANES as usize]) -> v4l2_buffer {
+        let mut v4l2_buf = v4l2_buffer {
+            type_: self.buf_type as u32,
+            memory: self.memory(),
+            ..unsafe { mem::zeroed() }
+        };
+        if self.mplane {
+            v4l2_buf.m.planes = planes.as_mut_ptr();
+            v4l2_buf.length = VIDEO_MAX_PLANES;
+        }
+
+        v4l2_buf
+    }
+
+    /// Returns the buffer type
+    pub fn buf_type(&self) -> BufType {
+        self.buf_type
+    }
+
+    /// Returns the device handle
+    pub fn handle(&self) -> &Arc<DeviceHandle> {
+        &self.handle
+    }
+
+    /// Returns the number of buffers
+    pub fn len(&self) -> usize {
+        self.bufs.len()
+    }
+
+    /// Returns the number of buffers currently owned by the driver, or waiting to be filled by
+    /// read() calls
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+
+    /// Returns the mapped memory of a buffer
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Buffer index
+    /// * `len` - Number of bytes to expose, clamped to the buffer length
+    pub fn buf(&self, index: usize, len: usize) -> &[u8] {
+        let buf = &self.bufs[index];
+        unsafe { slice::from_raw_parts(buf.ptr, usize::min(len, buf.len)) }
+    }
+
+    /// Returns the offset and length of each plane in the frame data of a buffer
+    ///
+    /// The layout is empty unless the buffer is multi-planar.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Buffer index
+    pub fn layout(&self, index: usize) -> Vec<(usize, usize)> {
+        self.bufs[index].layout.lock().unwrap().clone()
+    }
+
+    /// Hands a buffer to the driver
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Buffer index
+    pub fn queue(&self, index: usize) -> io::Result<()> {
+        if self.io == Io::Read {
+            self.free.lock().unwrap().push(index);
+            self.queued.fetch_add(1, Ordering::SeqCst);
+            self.returned();
+            return Ok(());
+        }
+
+        let buf = &self.bufs[index];
+        let mut v4l2_planes: [v4l2_plane; VIDEO_MAX_PLANES as usize] = unsafe { mem::zeroed() };
+        let mut v4l2_buf = v4l2_buffer {
+            index: index as u32,
+            ..self.buffer_desc(&mut v4l2_planes)
+        };
+        // the driver needs to be told where to put the data unless it owns the memory
+        match &buf.backing {
+            Backing::Mapped => {}
+            Backing::Planes(maps, _) => v4l2_buf.length = maps.len() as u32,
+            Backing::User(_) => {
+                debug_assert!(!self.mplane);
+                v4l2_buf.m.userptr = buf.ptr as std::os::raw::c_ulong;
+                v4l2_buf.length = buf.len as u32;
+            }
+            Backing::DmaBuf(fd) => {
+                debug_assert!(!self.mplane);
+                v4l2_buf.m.fd = fd.as_raw_fd();
+                v4l2_buf.length = buf.len as u32;
+            }
+        }
+        unsafe {
+            v4l2::ioctl(
+                self.handle.fd(),
+                v4l2::vidioc::VIDIOC_QBUF,
+                &mut v4l2_buf as *mut _ as *mut std::os::raw::c_void,
+            )?;
+        }
+
+        self.queued.fetch_add(1, Ordering::SeqCst);
+        self.returned();
+        Ok(())
+    }
+
+    /// Wakes up everyone waiting for a buffer to be handed back
+    fn returned(&self) {
+        // hold the lock, so waiters cannot miss the notification between checking and waiting
+        let _guard = self.returned_lock.lock().unwrap();
+        self.returned.notify_all();
+
+        #[cfg(feature = "async")]
+        if let Some(waker) = self.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+
+    /// Registers a task to be woken up once a buffer is handed back
+    ///
+    /// Returns `false` if a buffer has been handed back already, there is no need to wait then.
+    ///
+    /// # Arguments
+    ///
+    /// * `waker` - Waker of the task
+    #[cfg(feature = "async")]
+    pub fn register(&self, waker: &Waker) -> bool {
+        *self.waker.lock().unwrap() = Some(waker.clone());
+        // check again in case a buffer was returned before the waker was registered
+        self.queued() == 0
+    }
+
+    /// Takes a filled buffer from the driver, blocking until one is available
+    ///
+    /// If all buffers are lent out, this waits for one of them to be handed back first. Fails
+    /// with [`io::ErrorKind::TimedOut`] if no buffer was filled in time.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Maximum time to wait, `None` waits forever
+    pub fn dequeue(&self, timeout: Option<Duration>) -> io::Result<(usize, Metadata)> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        let mut guard = self.returned_lock.lock().unwrap();
+        while self.queued() == 0 {
+            guard = match deadline {
+                None => self.returned.wait(guard).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "timed out waiting for a buffer to be handed back",
+                        ));
+                    }
+                    self.returned.wait_timeout(guard, deadline - now).unwrap().0
+                }
+            };
+        }
+        drop(guard);
+
+        let timeout = match deadline {
+            Some(deadline) => {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX)
+            }
+            None => -1,
+        };
+        if self.handle.poll(libc::POLLIN, timeout)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "timed out waiting for a buffer",
+            ));
+        }
+
+        self.try_dequeue()
+    }
+
+    /// Takes a filled buffer from the driver without blocking
+    ///
+    /// Fails with [`io::ErrorKind::WouldBlock`] if no buffer is ready yet and with [`Exhausted`]
+    /// if all buffers are lent out.
+    pub fn try_dequeue(&self) -> io::Result<(usize, Metadata)> {
+        if self.queued() == 0 {
+            return Err(io::Error::other(Exhausted));
+        }
+
+        if self.io == Io::Read {
+            return self.read();
+        }
+
+        // the device is opened in non-blocking mode, so DQBUF fails with EAGAIN if no buffer
+        // has been filled yet
+        let mut v4l2_planes: [v4l2_plane; VIDEO_MAX_PLANES as usize] = unsafe { mem::zeroed() };
+        let mut v4l2_buf = self.buffer_desc(&mut v4l2_planes);
+        unsafe {
+            v4l2::ioctl(
+                self.handle.fd(),
+                v4l2::vidioc::VIDIOC_DQBUF,
+                &mut v4l2_buf as *mut _ as *mut std::os::raw::c_void,
+            )?;
+        }
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+
+        // For compressed formats, the buffer length will not actually describe the number of
+        // bytes in a frame. Instead, we have to explicitly query about the amount of used bytes.
+        let bytesused = if self.mplane {
+            let planes = &v4l2_planes[..v4l2_buf.length as usize];
+            self.gather(v4l2_buf.index as usize, planes)
+        } else {
+            v4l2_buf.bytesused as usize
+        };
+        let meta = Metadata {
+            timestamp: Timestamp::from(v4l2_buf.timestamp).into(),
+            sequence: v4l2_buf.sequence,
+            bytesused,
+            corrupted: BufFlags::from(v4l2_buf.flags).contains(BufFlags::ERROR),
+            buffer: Some(v4l2_buf.index as usize),
+        };
+        Ok((v4l2_buf.index as usize, meta))
+    }
+
+    /// Records the plane layout of a dequeued multi-planar buffer, returns the number of bytes
+    /// in the frame
+    ///
+    /// Planes are copied into the staging buffer if there are several of them. We own the buffer
+    /// until it is queued again, so nobody reads from the staging buffer while we write to it.
+    fn gather(&self, index: usize, planes: &[v4l2_plane]) -> usize {
+        let buf = &self.bufs[index];
+        let mut layout = buf.layout.lock().unwrap();
+        layout.clear();
+
+        let maps = match &buf.backing {
+            Backing::Planes(maps, Some(_)) => maps,
+            _ => {
+                // the frame data starts at the beginning of the single plane
+                let plane = &planes[0];
+                let bytesused = usize::min(plane.bytesused as usize, buf.len);
+                let offset = usize::min(plane.data_offset as usize, bytesused);
+                layout.push((offset, bytesused - offset));
+                return bytesused;
+            }
+        };
+
+        let mut len = 0;
+        for (plane, (ptr, size)) in planes.iter().zip(maps) {
+            let bytesused = usize::min(plane.bytesused as usize, *size);
+            let offset = usize::min(plane.data_offset as usize, bytesused);
+            unsafe {
+                ptr::copy_nonoverlapping(ptr.add(offset), buf.ptr.add(len), bytesused - offset);
+            }
+            layout.push((len, bytesused - offset));
+            len += bytesused - offset;
+        }
+
+        len
+    }
+
+    /// Reads a frame into a free buffer without blocking
+    fn read(&self) -> io::Result<(usize, Metadata)> {
+        let index = match self.free.lock().unwrap().pop() {
+            Some(index) => index,
+            None => return Err(io::Error::other(Exhausted)),
+        };
+
+        // like DQBUF, read() fails with EAGAIN if no frame is ready yet
+        let buf = &self.bufs[index];
+        let n = unsafe {
+            libc::read(
+                self.handle.fd(),
+                buf.ptr as *mut std::os::raw::c_void,
+                buf.len,
+            )
+        };
+        if n < 0 {
+            let err = io::Error::last_os_error();
+            self.free.lock().unwrap().push(index);
+            return Err(err);
+        }
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+
+        // read() does not come with a timestamp, so take one that matches what drivers use for
+        // streaming
+        let mut ts: libc::timespec = unsafe { mem::zeroed() };
+        unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+        let meta = Metadata {
+            timestamp: Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32),
+            sequence: self.sequence.fetch_add(1, Ordering::SeqCst),
+            bytesused: n as usize,
+            corrupted: false,
+            buffer: Some(index),
+        };
+        Ok((index, meta))
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        for buf in &self.bufs {
+            let maps = match &buf.backing {
+                Backing::User(_) => continue,
+                Backing::Planes(maps, _) => maps.clone(),
+                _ => vec![(buf.ptr, buf.len)],
+            };
+
+            for (ptr, len) in maps {
+                unsafe {
+                    // ignore the result
+                    let _ = v4l2::munmap(ptr as *mut std::os::raw::c_void, len);
+                }
+            }
+        }
+
+        // Release the buffers before user memory is freed, which happens once we return. Ignore
+        // the result.
+        if self.io != Io::Read {
+            let _ = self.request(0);
+        }
+    }
+}
+
+/// Driver buffer leased to an owned frame
+///
+/// The buffer is handed back to the driver when the lease is dropped.
+pub struct Lease {
+    arena: Arc<Arena>,
+    index: usize,
+    len: usize,
+}
+
+impl Lease {
+    pub fn new(arena: Arc<Arena>, index: usize, len: usize) -> Self {
+        Lease { arena, index, len }
+    }
+}
+
+impl AsRef<[u8]> for Lease {
+    fn as_ref(&self) -> &[u8] {
+        self.arena.buf(self.index, self.len)
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        // ignore the result, the stream may be gone already
+        let _ = self.arena.queue(self.index);
+    }
+}
@@ -1,48 +1,147 @@
+use std::mem;
+
+use v4l::buffer::Type as BufType;
+use v4l::capability::Flags as CapFlags;
 use v4l::context;
+use v4l::v4l2;
+use v4l::v4l_sys::v4l2_requestbuffers;
+use v4l::Memory;
 
 use crate::device;
 use crate::error::{Error, ErrorKind, Result};
 use crate::platform::v4l2::device::Handle as DeviceHandle;
+use crate::platform::v4l2::io_error;
+use crate::platform::v4l2::monitor::Monitor;
+use crate::stream::Io;
 use crate::traits::Context as ContextTrait;
 
+// memory types reported by VIDIOC_REQBUFS, see videodev2.h
+const BUF_CAP_SUPPORTS_MMAP: u32 = 1 << 0;
+const BUF_CAP_SUPPORTS_USERPTR: u32 = 1 << 1;
+const BUF_CAP_SUPPORTS_DMABUF: u32 = 1 << 2;
+
 /// Runtime context
 pub struct Context {}
 
+impl Context {
+    /// Returns a monitor which reports devices being connected and disconnected
+    pub fn monitor(&self) -> Result<Monitor> {
+        Ok(Monitor::new()?)
+    }
+
+    /// Opens a device with the given access mode
+    ///
+    /// # Arguments
+    ///
+    /// * `uri` - Device URI
+    /// * `access` - Access mode
+    pub fn open_device_with(&self, uri: &str, access: device::Access) -> Result<DeviceHandle> {
+        let mut dev = self.open_device(uri)?;
+        dev.set_access(access)?;
+        Ok(dev)
+    }
+}
+
+/// Returns the description of a capture node, if it is usable by us
+///
+/// # Arguments
+///
+/// * `index` - Index of the node, e.g. 0 for /dev/video0
+pub(crate) fn describe(index: usize) -> Option<device::Description> {
+    let dev = match DeviceHandle::new(index) {
+        Ok(dev) => dev,
+        Err(_) => return None,
+    };
+
+    let caps = match dev.inner().query_caps() {
+        Ok(caps) => caps,
+        Err(_) => return None,
+    };
+
+    // Require video capture and at least one I/O method. Every recent (released during the last
+    // ten to twenty years) webcam should support streaming, but very old devices and some
+    // virtual ones only support read() calls. Some SoC capture units only implement the
+    // multi-planar API.
+    let capture = caps.capabilities.contains(CapFlags::VIDEO_CAPTURE)
+        || caps.capabilities.contains(CapFlags::VIDEO_CAPTURE_MPLANE);
+    let io = io_methods(&dev, caps.capabilities);
+    if !capture || io.is_empty() {
+        return None;
+    }
+
+    Some(device::Description {
+        uri: format!("v4l:///dev/video{}", index),
+        product: caps.card,
+        io,
+    })
+}
+
+/// Returns the I/O methods supported by a capture node
+///
+/// # Arguments
+///
+/// * `dev` - Device handle
+/// * `caps` - Device capabilities
+fn io_methods(dev: &DeviceHandle, caps: CapFlags) -> Vec<Io> {
+    let mut io = Vec::new();
+
+    // we only capture multi-planar frames into buffers allocated by the driver
+    if dev.buf_type() as u32 == BufType::VideoCaptureMplane as u32 {
+        if caps.contains(CapFlags::STREAMING) {
+            io.push(Io::Mmap);
+        }
+        return io;
+    }
+
+    if caps.contains(CapFlags::STREAMING) {
+        // Since Linux 4.20, drivers report the supported memory types when buffers are requested.
+        // Requesting zero buffers does not allocate anything.
+        let mut v4l2_reqbufs = v4l2_requestbuffers {
+            count: 0,
+            type_: BufType::VideoCapture as u32,
+            memory: Memory::Mmap as u32,
+            ..unsafe { mem::zeroed() }
+        };
+        let res = unsafe {
+            v4l2::ioctl(
+                dev.inner().handle().fd(),
+                v4l2::vidioc::VIDIOC_REQBUFS,
+                &mut v4l2_reqbufs as *mut _ as *mut std::os::raw::c_void,
+            )
+        };
+
+        match res {
+            Ok(()) if v4l2_reqbufs.capabilities != 0 => {
+                let bits = v4l2_reqbufs.capabilities;
+                if bits & BUF_CAP_SUPPORTS_MMAP != 0 {
+                    io.push(Io::Mmap);
+                }
+                if bits & BUF_CAP_SUPPORTS_USERPTR != 0 {
+                    io.push(Io::UserPtr);
+                }
+                if bits & BUF_CAP_SUPPORTS_DMABUF != 0 {
+                    io.push(Io::DmaBuf);
+                }
+            }
+            // older kernels do not tell, but memory mapping is supported by virtually all drivers
+            _ => io.push(Io::Mmap),
+        }
+    }
+
+    if caps.contains(CapFlags::READ_WRITE) {
+        io.push(Io::Read);
+    }
+
+    io
+}
+
 impl<'a> ContextTrait<'a> for Context {
     type Device = DeviceHandle;
 
     fn devices(&self) -> Result<Vec<device::Description>> {
         let nodes = context::enum_devices()
             .into_iter()
-            .filter_map(|dev| {
-                let index = dev.index();
-                let dev = match DeviceHandle::new(index) {
-                    Ok(dev) => dev,
-                    Err(_) => return None,
-                };
-
-                let caps = match dev.inner().query_caps() {
-                    Ok(caps) => caps,
-                    Err(_) => return None,
-                };
-
-                // For now, require video capture and streaming capabilities.
-                // Very old devices may only support the read() I/O mechanism, so support for those
-                // might be added in the future. Every recent (released during the last ten to twenty
-                // years) webcam should support streaming though.
-                let capture_flag = v4l::capability::Flags::VIDEO_CAPTURE;
-                let streaming_flag = v4l::capability::Flags::STREAMING;
-                if caps.capabilities & capture_flag != capture_flag
-                    || caps.capabilities & streaming_flag != streaming_flag
-                {
-                    return None;
-                }
-
-                Some(device::Description {
-                    uri: format!("v4l:///dev/video{}", index),
-                    product: caps.card,
-                })
-            })
+            .filter_map(|dev| describe(dev.index()))
             .collect();
 
         Ok(nodes)
@@ -50,7 +149,7 @@ impl<'a> ContextTrait<'a> for Context {
 
     fn open_device(&self, uri: &str) -> Result<Self::Device> {
         if uri.starts_with("v4l://") {
-            let handle = crate::platform::v4l2::device::Handle::with_uri(uri)?;
+            let handle = crate::platform::v4l2::device::Handle::with_uri(uri).map_err(io_error)?;
             Ok(handle)
         } else {
             Err(Error::new(ErrorKind::Other, "invalid URI"))
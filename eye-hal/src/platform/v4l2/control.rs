@@ -0,0 +1,538 @@
+use std::convert::TryFrom;
+use std::ffi::CStr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{io, mem};
+
+use v4l::control::{Description, MenuItem, Type as ControlType};
+use v4l::device::Handle as DeviceHandle;
+use v4l::v4l2;
+use v4l::v4l_sys::*;
+
+use crate::control;
+use crate::error::{Error, ErrorKind, Result};
+use crate::platform::v4l2::io_error;
+
+// The bindgen representation of v4l2_ext_controls hides the 'which' member in an anonymous union
+// which we cannot conveniently access, so we mirror the struct here just like the v4l crate does.
+#[repr(C)]
+struct ExtControls {
+    which: u32,
+    count: u32,
+    error_idx: u32,
+    request_fd: i32,
+    reserved: u32,
+    controls: *mut v4l2_ext_control,
+}
+
+// Control IDs as defined in linux/v4l2-controls.h. Sensor and ISP drivers tend to expose the
+// generic exposure and the analogue gain controls rather than their UVC counterparts. When several
+// controls map to the same kind, the first entry takes precedence, so the absolute exposure and
+// the analogue gain are listed ahead of their generic counterparts.
+#[rustfmt::skip]
+const KINDS: [(u32, control::Kind); 19] = [
+    (0x0098_0900, control::Kind::Brightness),
+    (0x0098_0901, control::Kind::Contrast),
+    (0x0098_0902, control::Kind::Saturation),
+    (0x0098_0903, control::Kind::Hue),
+    (0x0098_090c, control::Kind::AutoWhiteBalance),
+    (0x0098_0910, control::Kind::Gamma),
+    (0x009a_0902, control::Kind::Exposure),
+    (0x0098_0911, control::Kind::Exposure),
+    (0x0098_0912, control::Kind::AutoGain),
+    (0x009e_0903, control::Kind::Gain),
+    (0x0098_0913, control::Kind::Gain),
+    (0x0098_0918, control::Kind::PowerLineFrequency),
+    (0x0098_091a, control::Kind::WhiteBalance),
+    (0x0098_091b, control::Kind::Sharpness),
+    (0x0098_091c, control::Kind::BacklightCompensation),
+    (0x009a_0901, control::Kind::AutoExposure),
+    (0x009a_090a, control::Kind::Focus),
+    (0x009a_090c, control::Kind::AutoFocus),
+    (0x009a_090d, control::Kind::Zoom),
+];
+
+/// Returns the well-known meaning of a control ID, if any
+pub fn kind(id: u32) -> Option<control::Kind> {
+    KINDS
+        .iter()
+        .find(|(cid, _)| *cid == id)
+        .map(|(_, kind)| *kind)
+}
+
+/// Returns the control IDs of a well-known kind, in order of precedence
+pub fn ids(kind: control::Kind) -> impl Iterator<Item = u32> {
+    KINDS
+        .iter()
+        .filter(move |(_, k)| *k == kind)
+        .map(|(cid, _)| *cid)
+}
+
+/// Returns the physical unit of a control ID, if known
+pub fn scale(id: u32) -> Option<control::Scale> {
+    match id {
+        // V4L2_CID_WHITE_BALANCE_TEMPERATURE
+        0x0098_091a => Some(control::Scale::new(control::Unit::Kelvin, 1.0)),
+        // V4L2_CID_EXPOSURE_ABSOLUTE, given in units of 100µs
+        0x009a_0902 => Some(control::Scale::new(control::Unit::Microseconds, 100.0)),
+        _ => None,
+    }
+}
+
+/// Converts V4L2 control flags
+pub fn flags(plat_flags: v4l::control::Flags) -> control::Flags {
+    // assume controls to be readable and writable by default
+    let mut flags = control::Flags::READ | control::Flags::WRITE;
+
+    if plat_flags & v4l::control::Flags::READ_ONLY == v4l::control::Flags::READ_ONLY {
+        flags.remove(control::Flags::WRITE);
+        flags.insert(control::Flags::READ);
+    }
+    if plat_flags & v4l::control::Flags::WRITE_ONLY == v4l::control::Flags::WRITE_ONLY {
+        flags.remove(control::Flags::READ);
+        flags.insert(control::Flags::WRITE);
+    }
+    if plat_flags & v4l::control::Flags::GRABBED == v4l::control::Flags::GRABBED {
+        flags.remove(control::Flags::WRITE);
+    }
+    if plat_flags & v4l::control::Flags::INACTIVE == v4l::control::Flags::INACTIVE {
+        flags.insert(control::Flags::INACTIVE);
+    }
+
+    flags
+}
+
+/// Queries the extended description of a control
+///
+/// # Arguments
+///
+/// * `handle` - Device handle
+/// * `id` - Control identifier
+pub fn query(handle: &DeviceHandle, id: u32) -> io::Result<v4l2_query_ext_ctrl> {
+    let mut v4l2_query = v4l2_query_ext_ctrl {
+        id,
+        ..unsafe { mem::zeroed() }
+    };
+    unsafe {
+        v4l2::ioctl(
+            handle.fd(),
+            v4l2::vidioc::VIDIOC_QUERY_EXT_CTRL,
+            &mut v4l2_query as *mut _ as *mut std::os::raw::c_void,
+        )?;
+    }
+
+    Ok(v4l2_query)
+}
+
+/// Describes a single control, including its menu items
+///
+/// # Arguments
+///
+/// * `handle` - Device handle
+/// * `id` - Control identifier
+pub fn describe(handle: &DeviceHandle, id: u32) -> io::Result<Description> {
+    let v4l2_query = query(handle, id)?;
+    let mut desc = Description::from(v4l2_query);
+
+    if desc.typ == ControlType::Menu || desc.typ == ControlType::IntegerMenu {
+        let mut items = Vec::new();
+        for index in (v4l2_query.minimum..=v4l2_query.maximum).step_by(v4l2_query.step as usize) {
+            let mut v4l2_menu = v4l2_querymenu {
+                id,
+                index: index as u32,
+                ..unsafe { mem::zeroed() }
+            };
+            let res = unsafe {
+                v4l2::ioctl(
+                    handle.fd(),
+                    v4l2::vidioc::VIDIOC_QUERYMENU,
+                    &mut v4l2_menu as *mut _ as *mut std::os::raw::c_void,
+                )
+            };
+            // drivers may leave gaps between the minimum and maximum index, just like in
+            // v4l::Device::query_controls
+            if res.is_err() {
+                continue;
+            }
+
+            if let Ok(item) = MenuItem::try_from((desc.typ, v4l2_menu)) {
+                items.push((v4l2_menu.index, item));
+            }
+        }
+        desc.items = Some(items);
+    }
+
+    Ok(desc)
+}
+
+/// Returns the type of a queried control
+pub fn typ(query: &v4l2_query_ext_ctrl) -> Result<ControlType> {
+    ControlType::try_from(query.type_)
+        .map_err(|_| Error::new(ErrorKind::NotSupported, "unknown control type"))
+}
+
+fn ext_ctrls(
+    handle: &DeviceHandle,
+    request: v4l2::vidioc::_IOC_TYPE,
+    which: u32,
+    ctrls: &mut [v4l2_ext_control],
+) -> Result<()> {
+    let mut v4l2_ctrls = ExtControls {
+        which,
+        count: ctrls.len() as u32,
+        error_idx: 0,
+        request_fd: 0,
+        reserved: 0,
+        controls: ctrls.as_mut_ptr(),
+    };
+    unsafe {
+        v4l2::ioctl(
+            handle.fd(),
+            request,
+            &mut v4l2_ctrls as *mut _ as *mut std::os::raw::c_void,
+        )
+        .map_err(io_error)?;
+    }
+
+    Ok(())
+}
+
+/// Reads the current value of a control
+///
+/// # Arguments
+///
+/// * `handle` - Device handle
+/// * `id` - Control identifier
+pub fn get(handle: &DeviceHandle, id: u32) -> Result<control::State> {
+    read(handle, id, V4L2_CTRL_WHICH_CUR_VAL)
+}
+
+/// Reads the default value of a control
+///
+/// # Arguments
+///
+/// * `handle` - Device handle
+/// * `id` - Control identifier
+pub fn default(handle: &DeviceHandle, id: u32) -> Result<control::State> {
+    read(handle, id, V4L2_CTRL_WHICH_DEF_VAL)
+}
+
+fn read(handle: &DeviceHandle, id: u32, which: u32) -> Result<control::State> {
+    let query = query(handle, id).map_err(io_error)?;
+    let typ = typ(&query)?;
+
+    // Buttons do not have a value, reading them fails with EACCES.
+    if typ == ControlType::Button {
+        return Ok(control::State::None);
+    }
+
+    let mut v4l2_ctrl = v4l2_ext_control {
+        id,
+        ..unsafe { mem::zeroed() }
+    };
+
+    // The string buffer must be large enough to hold the longest possible value including the
+    // terminating NUL byte, which is what the driver reports as element size.
+    let mut string = Vec::new();
+    if typ == ControlType::String {
+        string.resize(query.elem_size as usize, 0u8);
+        v4l2_ctrl.size = query.elem_size;
+        v4l2_ctrl.__bindgen_anon_1.string = string.as_mut_ptr() as *mut std::os::raw::c_char;
+    }
+
+    ext_ctrls(
+        handle,
+        v4l2::vidioc::VIDIOC_G_EXT_CTRLS,
+        which,
+        std::slice::from_mut(&mut v4l2_ctrl),
+    )?;
+
+    let state = unsafe {
+        match typ {
+            ControlType::Integer => control::State::Number(v4l2_ctrl.__bindgen_anon_1.value as f64),
+            ControlType::Integer64 => control::State::Integer(v4l2_ctrl.__bindgen_anon_1.value64),
+            ControlType::Boolean => control::State::Boolean(v4l2_ctrl.__bindgen_anon_1.value != 0),
+            ControlType::Menu | ControlType::IntegerMenu => {
+                control::State::Menu(v4l2_ctrl.__bindgen_anon_1.value as u32)
+            }
+            ControlType::Bitmask => {
+                control::State::Bitmask(v4l2_ctrl.__bindgen_anon_1.value as u32)
+            }
+            ControlType::String => {
+                let value = CStr::from_bytes_until_nul(&string).map_err(|_| {
+                    Error::new(ErrorKind::Other, "string control is not terminated")
+                })?;
+                control::State::String(value.to_string_lossy().into_owned())
+            }
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::NotSupported,
+                    "control type not supported",
+                ))
+            }
+        }
+    };
+
+    Ok(state)
+}
+
+/// Writes the value of a control
+///
+/// # Arguments
+///
+/// * `handle` - Device handle
+/// * `id` - Control identifier
+/// * `state` - New value
+pub fn set(handle: &DeviceHandle, id: u32, state: &control::State) -> Result<()> {
+    set_all(handle, &[(id, state.clone())])
+}
+
+/// Writes the values of multiple controls at once
+///
+/// The driver validates all values before applying any of them, so either all or none of them
+/// are set unless the hardware itself fails.
+///
+/// # Arguments
+///
+/// * `handle` - Device handle
+/// * `ctrls` - Control identifiers and their new values
+pub fn set_all(handle: &DeviceHandle, ctrls: &[(u32, control::State)]) -> Result<()> {
+    // keeps the strings alive until the ioctl is done
+    let mut strings = Vec::new();
+    let mut v4l2_ctrls = Vec::with_capacity(ctrls.len());
+    for (id, state) in ctrls {
+        v4l2_ctrls.push(encode(handle, *id, state, &mut strings)?);
+    }
+
+    ext_ctrls(
+        handle,
+        v4l2::vidioc::VIDIOC_S_EXT_CTRLS,
+        V4L2_CTRL_WHICH_CUR_VAL,
+        &mut v4l2_ctrls,
+    )
+}
+
+fn encode(
+    handle: &DeviceHandle,
+    id: u32,
+    state: &control::State,
+    strings: &mut Vec<Vec<u8>>,
+) -> Result<v4l2_ext_control> {
+    let query = query(handle, id).map_err(io_error)?;
+    let typ = typ(&query)?;
+
+    let mut v4l2_ctrl = v4l2_ext_control {
+        id,
+        ..unsafe { mem::zeroed() }
+    };
+
+    match (typ, state) {
+        (ControlType::Button, control::State::None) => {}
+        (
+            ControlType::Integer | ControlType::Menu | ControlType::IntegerMenu,
+            control::State::Number(value),
+        ) => v4l2_ctrl.__bindgen_anon_1.value = *value as i32,
+        (
+            ControlType::Integer | ControlType::Menu | ControlType::IntegerMenu,
+            control::State::Integer(value),
+        ) => {
+            v4l2_ctrl.__bindgen_anon_1.value = i32::try_from(*value).map_err(|_| {
+                Error::new(ErrorKind::Other, "control value exceeds the 32-bit range")
+            })?
+        }
+        (ControlType::Menu | ControlType::IntegerMenu, control::State::Menu(index)) => {
+            v4l2_ctrl.__bindgen_anon_1.value = *index as i32
+        }
+        (ControlType::Integer64, control::State::Number(value)) => {
+            v4l2_ctrl.__bindgen_anon_1.value64 = *value as i64
+        }
+        (ControlType::Integer64, control::State::Integer(value)) => {
+            v4l2_ctrl.__bindgen_anon_1.value64 = *value
+        }
+        (ControlType::Boolean, control::State::Boolean(value)) => {
+            v4l2_ctrl.__bindgen_anon_1.value = *value as i32
+        }
+        (ControlType::Bitmask, control::State::Bitmask(value)) => {
+            v4l2_ctrl.__bindgen_anon_1.value = *value as i32
+        }
+        (ControlType::String, control::State::String(value)) => {
+            // The size has to include the terminating NUL byte, otherwise the driver cuts off
+            // the last character.
+            let mut bytes = value.clone().into_bytes();
+            bytes.push(0);
+            v4l2_ctrl.size = bytes.len() as u32;
+            v4l2_ctrl.__bindgen_anon_1.string = bytes.as_ptr() as *mut std::os::raw::c_char;
+            // moving the vector does not move its heap buffer
+            strings.push(bytes);
+        }
+        _ => {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "control value cannot be mapped",
+            ))
+        }
+    }
+
+    Ok(v4l2_ctrl)
+}
+
+// The v4l crate does not define the event ioctls, see linux/videodev2.h.
+const fn ioc(dir: u32, nr: u32, size: usize) -> v4l2::vidioc::_IOC_TYPE {
+    ((dir << 30) | ((size as u32) << 16) | ((b'V' as u32) << 8) | nr) as v4l2::vidioc::_IOC_TYPE
+}
+const VIDIOC_DQEVENT: v4l2::vidioc::_IOC_TYPE = ioc(2, 89, mem::size_of::<v4l2_event>());
+const VIDIOC_SUBSCRIBE_EVENT: v4l2::vidioc::_IOC_TYPE =
+    ioc(1, 90, mem::size_of::<v4l2_event_subscription>());
+
+/// Control event subscription
+///
+/// The driver publishes an event whenever the value, flags or range of a subscribed control
+/// change, including changes made through the device handle. Events are queued per open file
+/// description, so each subscription opens the device node on its own and does not interfere
+/// with others. Closing it on drop cancels the subscription.
+pub struct Subscription {
+    handle: Arc<DeviceHandle>,
+}
+
+impl Subscription {
+    /// Subscribes to control events
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the device node
+    /// * `ids` - Control identifiers
+    pub fn new<P: AsRef<Path>>(path: P, ids: &[u32]) -> Result<Self> {
+        let dev = v4l::Device::with_path(path).map_err(io_error)?;
+        let sub = Subscription {
+            handle: dev.handle(),
+        };
+
+        for id in ids {
+            sub.ioctl(VIDIOC_SUBSCRIBE_EVENT, *id).map_err(io_error)?;
+        }
+
+        Ok(sub)
+    }
+
+    fn ioctl(&self, request: v4l2::vidioc::_IOC_TYPE, id: u32) -> io::Result<()> {
+        let mut v4l2_sub = v4l2_event_subscription {
+            type_: V4L2_EVENT_CTRL,
+            id,
+            flags: V4L2_EVENT_SUB_FL_ALLOW_FEEDBACK,
+            ..unsafe { mem::zeroed() }
+        };
+        unsafe {
+            v4l2::ioctl(
+                self.handle.fd(),
+                request,
+                &mut v4l2_sub as *mut _ as *mut std::os::raw::c_void,
+            )
+        }
+    }
+
+    fn dequeue(&self) -> Result<Option<control::Event>> {
+        let mut v4l2_event: v4l2_event = unsafe { mem::zeroed() };
+        let res = unsafe {
+            v4l2::ioctl(
+                self.handle.fd(),
+                VIDIOC_DQEVENT,
+                &mut v4l2_event as *mut _ as *mut std::os::raw::c_void,
+            )
+        };
+        match res {
+            Ok(()) => {}
+            // no event pending
+            Err(e) if e.raw_os_error() == Some(libc::ENOENT) => return Ok(None),
+            Err(e) => return Err(io_error(e)),
+        }
+
+        let ev = unsafe { v4l2_event.u.ctrl };
+        let typ = ControlType::try_from(ev.type_).ok();
+        let value = unsafe {
+            match typ {
+                Some(ControlType::Integer) => {
+                    Some(control::State::Number(ev.__bindgen_anon_1.value as f64))
+                }
+                Some(ControlType::Integer64) => {
+                    Some(control::State::Integer(ev.__bindgen_anon_1.value64))
+                }
+                Some(ControlType::Boolean) => {
+                    Some(control::State::Boolean(ev.__bindgen_anon_1.value != 0))
+                }
+                Some(ControlType::Menu | ControlType::IntegerMenu) => {
+                    Some(control::State::Menu(ev.__bindgen_anon_1.value as u32))
+                }
+                Some(ControlType::Bitmask) => {
+                    Some(control::State::Bitmask(ev.__bindgen_anon_1.value as u32))
+                }
+                // strings are not part of the event payload
+                Some(ControlType::String) => get(&self.handle, v4l2_event.id).ok(),
+                _ => None,
+            }
+        };
+        let (range, step) = match typ {
+            Some(ControlType::Integer | ControlType::Integer64) => (
+                Some((ev.minimum as f64, ev.maximum as f64)),
+                Some(ev.step as f32),
+            ),
+            _ => (None, None),
+        };
+
+        Ok(Some(control::Event {
+            id: v4l2_event.id,
+            changes: control::Changes::from_bits_truncate(ev.changes),
+            value,
+            flags: flags(v4l::control::Flags::from(ev.flags)),
+            range,
+            step,
+        }))
+    }
+
+    /// Returns the next event, waiting at most `timeout` for it
+    ///
+    /// Returns an error of kind [`ErrorKind::Timeout`] if nothing changed in time.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<Result<control::Event>> {
+        self.wait(Some(timeout))
+    }
+
+    fn wait(&mut self, timeout: Option<Duration>) -> Option<Result<control::Event>> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        loop {
+            match self.dequeue() {
+                Ok(Some(event)) => return Some(Ok(event)),
+                Ok(None) => {}
+                Err(e) => return Some(Err(e)),
+            }
+
+            // pending events are signaled as exceptional condition
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    i32::try_from(remaining.as_millis()).unwrap_or(i32::MAX)
+                }
+                None => -1,
+            };
+            match self.handle.poll(libc::POLLPRI, timeout) {
+                Ok(0) => {
+                    return Some(Err(Error::new(
+                        ErrorKind::Timeout,
+                        "timed out waiting for a control event",
+                    )))
+                }
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Some(Err(io_error(e))),
+            }
+        }
+    }
+}
+
+impl Iterator for Subscription {
+    type Item = Result<control::Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.wait(None)
+    }
+}
@@ -1,30 +1,51 @@
-use std::{convert::TryInto, io, path::Path, time::Duration};
+use std::path::{Path, PathBuf};
+use std::{convert::TryInto, io, mem};
 
-use v4l::control::{
-    Control, MenuItem as ControlMenuItem, Type as ControlType, Value as ControlValue,
+use v4l::buffer::Type as BufType;
+use v4l::capability::Flags as CapFlags;
+use v4l::control::{MenuItem as ControlMenuItem, Type as ControlType};
+use v4l::frameinterval::FrameIntervalEnum;
+use v4l::framesize::FrameSizeEnum;
+use v4l::parameters::Capabilities as ParamCapabilities;
+use v4l::v4l2;
+use v4l::v4l_sys::{
+    v4l2_format, v4l2_format__bindgen_ty_1, v4l2_frmivaltypes_V4L2_FRMIVAL_TYPE_CONTINUOUS,
+    v4l2_frmsizetypes_V4L2_FRMSIZE_TYPE_CONTINUOUS, v4l2_priority,
+    v4l2_priority_V4L2_PRIORITY_BACKGROUND, v4l2_priority_V4L2_PRIORITY_DEFAULT,
+    v4l2_priority_V4L2_PRIORITY_RECORD, v4l2_requestbuffers,
 };
 use v4l::video::Capture;
 use v4l::Device as CaptureDevice;
 use v4l::Format as CaptureFormat;
 use v4l::FourCC as FourCC_;
+use v4l::Memory;
 
 use crate::control;
+use crate::device::{Access, State};
 use crate::error::{Error, ErrorKind, Result};
-use crate::format::PixelFormat;
+use crate::format::{ImageFormat, PixelFormat};
+use crate::platform::v4l2::control as v4l2_control;
+use crate::platform::v4l2::io_error;
+use crate::platform::v4l2::mplane;
 use crate::platform::v4l2::stream::Handle as StreamHandle;
-use crate::stream::Descriptor as StreamDescriptor;
+use crate::stream::{
+    Buffers as StreamBuffers, Descriptor as StreamDescriptor, Fraction, Io as StreamIo,
+    Options as StreamOptions, RangeDescriptor as StreamRangeDescriptor, Stepwise,
+};
 use crate::traits::Device;
 
 pub struct Handle {
     inner: CaptureDevice,
+    // whether the device only offers the multi-planar API
+    mplane: bool,
+    // device node, reopened to probe the state without disturbing our own file description
+    path: PathBuf,
+    access: Access,
 }
 
 impl Handle {
     pub fn new(index: usize) -> io::Result<Self> {
-        let dev = Handle {
-            inner: CaptureDevice::new(index)?,
-        };
-        Ok(dev)
+        Self::with_path(format!("/dev/video{}", index))
     }
 
     pub fn with_uri<S: Into<String>>(uri: S) -> io::Result<Self> {
@@ -37,45 +58,633 @@ impl Handle {
     }
 
     pub fn with_path<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        let dev = Handle {
-            inner: CaptureDevice::with_path(path)?,
+        let path = path.as_ref().to_path_buf();
+        let inner = CaptureDevice::with_path(&path)?;
+
+        // prefer the single-planar API if the device offers both
+        let caps = inner.query_caps()?.capabilities;
+        let mplane = !caps.contains(CapFlags::VIDEO_CAPTURE)
+            && caps.contains(CapFlags::VIDEO_CAPTURE_MPLANE);
+
+        Ok(Handle {
+            inner,
+            mplane,
+            path,
+            access: Access::Shared,
+        })
+    }
+
+    /// Changes the access mode
+    ///
+    /// Exclusive access raises the V4L2 priority of the handle, so other clients cannot change
+    /// the format or start streaming. It also takes an advisory lock on the device node, which
+    /// prevents cooperating clients from claiming exclusive access at the same time. Both are
+    /// released when the handle is dropped.
+    ///
+    /// Returns an error of kind [`crate::ErrorKind::InUse`] if another client has exclusive
+    /// access. The access mode is left unchanged on error.
+    ///
+    /// # Arguments
+    ///
+    /// * `access` - Access mode
+    pub fn set_access(&mut self, access: Access) -> Result<()> {
+        if access == self.access {
+            return Ok(());
+        }
+
+        let fd = self.inner.handle().fd();
+        let claim = access == Access::Exclusive;
+        if claim {
+            lock(fd, libc::LOCK_EX)?;
+        }
+
+        if let Err(e) = set_access(fd, access) {
+            if claim {
+                // ignore the result
+                let _ = lock(fd, libc::LOCK_UN);
+            }
+            return Err(e);
+        }
+
+        if self.access == Access::Exclusive {
+            // ignore the result, the priority has been lowered already
+            let _ = lock(fd, libc::LOCK_UN);
+        }
+
+        self.access = access;
+        Ok(())
+    }
+
+    /// Returns whether the device is streaming or owned by another client
+    ///
+    /// Streams started through this handle are reported as well.
+    pub fn state(&self) -> Result<State> {
+        // probe through a new file description: locks and buffer ownership are tied to those
+        let probe = match CaptureDevice::with_path(&self.path) {
+            Ok(probe) => probe,
+            // some drivers only allow a single open file description
+            Err(e) if e.raw_os_error() == Some(libc::EBUSY) => return Ok(State::Busy),
+            Err(e) => return Err(io_error(e)),
+        };
+        let fd = probe.handle().fd();
+
+        // we cannot be locked out while holding exclusive access ourselves
+        if self.access != Access::Exclusive {
+            match lock(fd, libc::LOCK_SH) {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::InUse => return Ok(State::Busy),
+                Err(e) => return Err(e),
+            }
+            // not all drivers implement priorities
+            if priority(fd).ok() == Some(v4l2_priority_V4L2_PRIORITY_RECORD) {
+                return Ok(State::Busy);
+            }
+        }
+
+        // Requesting zero buffers does not allocate anything, but fails if the buffer queue is
+        // owned by another file description.
+        let mut v4l2_reqbufs = v4l2_requestbuffers {
+            count: 0,
+            type_: self.buf_type() as u32,
+            memory: Memory::Mmap as u32,
+            ..unsafe { mem::zeroed() }
         };
-        Ok(dev)
+        let res = unsafe {
+            v4l2::ioctl(
+                fd,
+                v4l2::vidioc::VIDIOC_REQBUFS,
+                &mut v4l2_reqbufs as *mut _ as *mut std::os::raw::c_void,
+            )
+        };
+
+        match res {
+            Err(e) if e.raw_os_error() == Some(libc::EBUSY) => Ok(State::Streaming),
+            // devices without streaming support cannot tell
+            _ => Ok(State::Idle),
+        }
+    }
+
+    /// Returns the buffer type used for capturing
+    pub fn buf_type(&self) -> BufType {
+        if self.mplane {
+            BufType::VideoCaptureMplane
+        } else {
+            BufType::VideoCapture
+        }
     }
 
     pub fn inner(&self) -> &CaptureDevice {
         &self.inner
     }
+
+    /// Subscribes to control change events
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - Control identifiers, all controls are watched if empty
+    pub fn subscribe_controls(&self, ids: &[u32]) -> Result<v4l2_control::Subscription> {
+        if ids.is_empty() {
+            let ids: Vec<u32> = self.controls()?.iter().map(|ctrl| ctrl.id).collect();
+            v4l2_control::Subscription::new(&self.path, &ids)
+        } else {
+            v4l2_control::Subscription::new(&self.path, ids)
+        }
+    }
+
+    /// Returns a stream which produces images
+    ///
+    /// The driver may adjust the requested format, the effective format is reported by the
+    /// stream. If `opts.strict` is set, adjustments are treated as error instead.
+    pub fn start_stream_with(
+        &self,
+        desc: &StreamDescriptor,
+        opts: &StreamOptions,
+    ) -> Result<StreamHandle> {
+        let (format, interval) = self.negotiate(desc, opts).map_err(in_use)?;
+        StreamHandle::new(self, format, interval, opts).map_err(in_use)
+    }
+
+    /// Returns a stream which captures into the given buffers
+    ///
+    /// Like [`Self::start_stream_with`], but the I/O method is determined by the type of buffers.
+    /// The buffer count in `opts` is ignored, the driver is offered all buffers in `bufs`.
+    pub fn start_stream_into(
+        &self,
+        desc: &StreamDescriptor,
+        opts: &StreamOptions,
+        bufs: StreamBuffers,
+    ) -> Result<StreamHandle> {
+        if opts.io != StreamIo::Auto && opts.io != bufs.io() {
+            return Err(Error::new(
+                ErrorKind::InvalidArgument,
+                "buffers do not match the requested I/O method",
+            ));
+        }
+
+        let (format, interval) = self.negotiate(desc, opts).map_err(in_use)?;
+        StreamHandle::with_buffers(self, format, interval, bufs, opts.policy).map_err(in_use)
+    }
+
+    /// Configures the frame format and timing, returns the values set by the driver
+    fn negotiate(
+        &self,
+        desc: &StreamDescriptor,
+        opts: &StreamOptions,
+    ) -> Result<(ImageFormat, Fraction)> {
+        let fourcc = if let Ok(fourcc) = desc.pixfmt.clone().try_into() {
+            fourcc
+        } else {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "failed to map pixelformat to fourcc",
+            ));
+        };
+        // In strict mode, find out whether the driver would adjust the request before changing
+        // anything, so a failed request leaves the device untouched.
+        if opts.strict {
+            self.try_negotiate(desc, fourcc)?;
+        }
+
+        // configure frame format
+        let (image_format, matched) = if self.mplane {
+            let handle = self.inner.handle();
+            let format =
+                mplane::set_format(&handle, desc.width, desc.height, FourCC_::new(&fourcc))
+                    .map_err(io_error)?;
+            // the image spans all memory planes, the stride refers to the first one
+            let stride = format.planes.first().map_or(0, |plane| plane.0);
+            let size = format.planes.iter().map(|plane| plane.1).sum::<u32>();
+            let image_format = ImageFormat::new(
+                format.width,
+                format.height,
+                PixelFormat::from(&format.fourcc.repr),
+            )
+            .stride(stride as usize)
+            .size(size as usize);
+            (image_format, format.fourcc.repr)
+        } else {
+            let format = CaptureFormat::new(desc.width, desc.height, FourCC_::new(&fourcc));
+            let format = self.inner.set_format(&format).map_err(io_error)?;
+            let image_format = ImageFormat::new(
+                format.width,
+                format.height,
+                PixelFormat::from(&format.fourcc.repr),
+            )
+            .stride(format.stride as usize)
+            .size(format.size as usize);
+            (image_format, format.fourcc.repr)
+        };
+
+        // configure frame timing
+        let mut params = if self.mplane {
+            mplane::params(&self.inner.handle()).map_err(io_error)?
+        } else {
+            self.inner.params().map_err(io_error)?
+        };
+        params.interval = v4l::Fraction::new(desc.interval.numerator, desc.interval.denominator);
+        let params = if self.mplane {
+            mplane::set_params(&self.inner.handle(), &params).map_err(io_error)?
+        } else {
+            self.inner.set_params(&params).map_err(io_error)?
+        };
+        // drivers which do not support frame timing leave the interval untouched
+        let interval = if params
+            .capabilities
+            .contains(ParamCapabilities::TIME_PER_FRAME)
+        {
+            Fraction::from(params.interval)
+        } else {
+            desc.interval
+        };
+
+        // some drivers round intervals without advertising it, which is only noticed here
+        if opts.strict
+            && (image_format.width != desc.width
+                || image_format.height != desc.height
+                || matched != fourcc
+                || interval != desc.interval)
+        {
+            return Err(Error::new(
+                ErrorKind::NotSupported,
+                format!(
+                    "driver adjusted the stream format to {}x{} {} @ {}s",
+                    image_format.width, image_format.height, image_format.pixfmt, interval
+                ),
+            ));
+        }
+
+        Ok((image_format, interval))
+    }
+
+    /// Checks whether the driver accepts a stream descriptor without adjusting it
+    ///
+    /// The format is checked with VIDIOC_TRY_FMT, the interval against the ones the driver
+    /// enumerates for the format, so the device configuration is not changed.
+    fn try_negotiate(&self, desc: &StreamDescriptor, fourcc: [u8; 4]) -> Result<()> {
+        let (width, height, matched) = if self.mplane {
+            let format = mplane::try_format(
+                &self.inner.handle(),
+                desc.width,
+                desc.height,
+                FourCC_::new(&fourcc),
+            )
+            .map_err(io_error)?;
+            (format.width, format.height, format.fourcc.repr)
+        } else {
+            let format = CaptureFormat::new(desc.width, desc.height, FourCC_::new(&fourcc));
+            let format = try_format(&self.inner.handle(), &format).map_err(io_error)?;
+            (format.width, format.height, format.fourcc.repr)
+        };
+
+        if width != desc.width || height != desc.height || matched != fourcc {
+            return Err(Error::new(
+                ErrorKind::NotSupported,
+                format!(
+                    "driver would adjust the stream format to {}x{} {}",
+                    width,
+                    height,
+                    PixelFormat::from(&matched)
+                ),
+            ));
+        }
+
+        // drivers which do not enumerate intervals are checked once the parameters are set
+        let intervals = self
+            .frameintervals(FourCC_::new(&fourcc), width, height)
+            .unwrap_or_default();
+        if !intervals.is_empty()
+            && !intervals
+                .iter()
+                .any(|interval| interval.contains(desc.interval))
+        {
+            return Err(Error::new(
+                ErrorKind::NotSupported,
+                format!(
+                    "frame interval {}s is not supported for {}x{} {}",
+                    desc.interval, width, height, desc.pixfmt
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Converts a V4L2 control description, returns None for controls we cannot handle
+fn descriptor(
+    handle: &v4l::device::Handle,
+    control: v4l::control::Description,
+) -> Option<control::Descriptor> {
+    // The v4l docs say applications should ignore permanently disabled controls.
+    if control.flags & v4l::control::Flags::DISABLED == v4l::control::Flags::DISABLED {
+        return None;
+    }
+
+    let state_type = match control.typ {
+        ControlType::Integer | ControlType::Integer64 => control::Type::Number {
+            range: (control.minimum as f64, control.maximum as f64),
+            step: control.step as f32,
+        },
+        ControlType::Boolean => control::Type::Boolean,
+        ControlType::Menu | ControlType::IntegerMenu => {
+            let mut items = Vec::new();
+            if let Some(plat_items) = control.items {
+                for (index, plat_item) in plat_items {
+                    let value = match plat_item {
+                        ControlMenuItem::Name(name) => control::MenuValue::String(name),
+                        ControlMenuItem::Value(value) => control::MenuValue::Number(value as f64),
+                    };
+                    items.push(control::MenuItem { index, value });
+                }
+            }
+            control::Type::Menu(items)
+        }
+        ControlType::Button => control::Type::Stateless,
+        ControlType::String => control::Type::String,
+        ControlType::Bitmask => control::Type::Bitmask,
+        _ => return None,
+    };
+
+    let flags = v4l2_control::flags(control.flags);
+
+    let default = match control.typ {
+        ControlType::Integer => Some(control::State::Number(control.default as f64)),
+        ControlType::Integer64 => Some(control::State::Integer(control.default)),
+        ControlType::Boolean => Some(control::State::Boolean(control.default != 0)),
+        ControlType::Menu | ControlType::IntegerMenu => {
+            Some(control::State::Menu(control.default as u32))
+        }
+        ControlType::Bitmask => Some(control::State::Bitmask(control.default as u32)),
+        // the query does not carry string defaults
+        ControlType::String => v4l2_control::default(handle, control.id).ok(),
+        _ => None,
+    };
+    let value = if flags.contains(control::Flags::READ) {
+        v4l2_control::get(handle, control.id).ok()
+    } else {
+        None
+    };
+
+    Some(control::Descriptor {
+        id: control.id,
+        name: control.name,
+        typ: state_type,
+        flags,
+        kind: v4l2_control::kind(control.id),
+        scale: v4l2_control::scale(control.id),
+        default,
+        value,
+    })
+}
+
+/// Returns the intervals contained in both ranges, if any
+fn overlap(a: &Stepwise<Fraction>, b: &Stepwise<Fraction>) -> Option<Stepwise<Fraction>> {
+    let min = a.min.max(b.min);
+    let max = a.max.min(b.max);
+    if min > max {
+        return None;
+    }
+
+    Some(Stepwise {
+        min,
+        max,
+        step: a.step,
+    })
+}
+
+/// Returns the single-planar format the driver would pick, without changing the device
+///
+/// # Arguments
+///
+/// * `handle` - Device handle
+/// * `fmt` - Requested format
+fn try_format(handle: &v4l::device::Handle, fmt: &CaptureFormat) -> io::Result<CaptureFormat> {
+    let mut v4l2_fmt = v4l2_format {
+        type_: BufType::VideoCapture as u32,
+        fmt: v4l2_format__bindgen_ty_1 { pix: (*fmt).into() },
+    };
+    unsafe {
+        v4l2::ioctl(
+            handle.fd(),
+            v4l2::vidioc::VIDIOC_TRY_FMT,
+            &mut v4l2_fmt as *mut _ as *mut std::os::raw::c_void,
+        )?;
+
+        Ok(CaptureFormat::from(v4l2_fmt.fmt.pix))
+    }
+}
+
+/// Maps errors caused by another client owning the device
+fn in_use(e: Error) -> Error {
+    if e.kind() == ErrorKind::Busy {
+        Error::new(
+            ErrorKind::InUse,
+            "device is owned by another client or stream",
+        )
+    } else {
+        e
+    }
+}
+
+/// Applies or removes an advisory lock without blocking
+///
+/// # Arguments
+///
+/// * `fd` - File descriptor
+/// * `op` - Lock operation, e.g. `libc::LOCK_EX`
+fn lock(fd: std::os::raw::c_int, op: std::os::raw::c_int) -> Result<()> {
+    if unsafe { libc::flock(fd, op | libc::LOCK_NB) } == 0 {
+        return Ok(());
+    }
+
+    let err = io::Error::last_os_error();
+    if err.raw_os_error() == Some(libc::EWOULDBLOCK) {
+        Err(Error::new(
+            ErrorKind::InUse,
+            "device is locked by another client",
+        ))
+    } else {
+        Err(err.into())
+    }
+}
+
+/// Sets the priority matching an access mode
+///
+/// # Arguments
+///
+/// * `fd` - File descriptor
+/// * `access` - Access mode
+fn set_access(fd: std::os::raw::c_int, access: Access) -> Result<()> {
+    let prio = match access {
+        Access::Background => v4l2_priority_V4L2_PRIORITY_BACKGROUND,
+        Access::Shared => v4l2_priority_V4L2_PRIORITY_DEFAULT,
+        Access::Exclusive => {
+            if priority(fd).map_err(io_error)? == v4l2_priority_V4L2_PRIORITY_RECORD {
+                return Err(Error::new(
+                    ErrorKind::InUse,
+                    "another client has exclusive access",
+                ));
+            }
+            v4l2_priority_V4L2_PRIORITY_RECORD
+        }
+    };
+
+    set_priority(fd, prio).map_err(io_error)
+}
+
+/// Returns the highest priority of all clients
+///
+/// # Arguments
+///
+/// * `fd` - File descriptor
+fn priority(fd: std::os::raw::c_int) -> io::Result<v4l2_priority> {
+    let mut prio: v4l2_priority = 0;
+    unsafe {
+        v4l2::ioctl(
+            fd,
+            v4l2::vidioc::VIDIOC_G_PRIORITY,
+            &mut prio as *mut _ as *mut std::os::raw::c_void,
+        )?;
+    }
+
+    Ok(prio)
+}
+
+/// Sets the priority of a file description
+///
+/// # Arguments
+///
+/// * `fd` - File descriptor
+/// * `prio` - Priority
+fn set_priority(fd: std::os::raw::c_int, mut prio: v4l2_priority) -> io::Result<()> {
+    unsafe {
+        v4l2::ioctl(
+            fd,
+            v4l2::vidioc::VIDIOC_S_PRIORITY,
+            &mut prio as *mut _ as *mut std::os::raw::c_void,
+        )
+    }
+}
+
+impl Handle {
+    /// Returns the pixel formats offered by the device
+    fn fourccs(&self) -> Result<Vec<FourCC_>> {
+        if self.mplane {
+            mplane::enum_formats(&self.inner.handle()).map_err(io_error)
+        } else {
+            Ok(self
+                .inner
+                .enum_formats()
+                .map_err(io_error)?
+                .into_iter()
+                .map(|format| format.fourcc)
+                .collect())
+        }
+    }
+
+    /// Returns the frame sizes supported for a pixel format
+    ///
+    /// Frame sizes are enumerated the same way for both APIs.
+    fn framesizes(&self, fourcc: FourCC_) -> Result<Vec<(Stepwise<u32>, Stepwise<u32>)>> {
+        let framesizes = self.inner.enum_framesizes(fourcc).map_err(io_error)?;
+        let sizes = framesizes
+            .into_iter()
+            .map(|framesize| match framesize.size {
+                FrameSizeEnum::Discrete(size) => (
+                    Stepwise::<u32>::discrete(size.width),
+                    Stepwise::<u32>::discrete(size.height),
+                ),
+                FrameSizeEnum::Stepwise(size) => {
+                    let continuous =
+                        framesize.typ == v4l2_frmsizetypes_V4L2_FRMSIZE_TYPE_CONTINUOUS;
+                    (
+                        Stepwise {
+                            min: size.min_width,
+                            max: size.max_width,
+                            step: if continuous { 0 } else { size.step_width },
+                        },
+                        Stepwise {
+                            min: size.min_height,
+                            max: size.max_height,
+                            step: if continuous { 0 } else { size.step_height },
+                        },
+                    )
+                }
+            })
+            .collect();
+
+        Ok(sizes)
+    }
+
+    /// Returns the frame intervals supported for a pixel format and frame size
+    fn frameintervals(
+        &self,
+        fourcc: FourCC_,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<Stepwise<Fraction>>> {
+        let frameintervals = self
+            .inner
+            .enum_frameintervals(fourcc, width, height)
+            .map_err(io_error)?;
+        let intervals = frameintervals
+            .into_iter()
+            .map(|frameinterval| match frameinterval.interval {
+                FrameIntervalEnum::Discrete(fraction) => {
+                    Stepwise::<Fraction>::discrete(Fraction::from(fraction))
+                }
+                FrameIntervalEnum::Stepwise(interval) => {
+                    let continuous =
+                        frameinterval.typ == v4l2_frmivaltypes_V4L2_FRMIVAL_TYPE_CONTINUOUS;
+                    Stepwise {
+                        min: Fraction::from(interval.min),
+                        max: Fraction::from(interval.max),
+                        step: if continuous {
+                            Fraction::new(0, 1)
+                        } else {
+                            Fraction::from(interval.step)
+                        },
+                    }
+                }
+            })
+            .collect();
+
+        Ok(intervals)
+    }
 }
 
 impl<'a> Device<'a> for Handle {
-    type Stream = StreamHandle<'a>;
+    type Stream = StreamHandle;
 
     fn streams(&self) -> Result<Vec<StreamDescriptor>> {
         let mut streams = Vec::new();
-        let plat_formats = self.inner.enum_formats()?;
-
-        for format in plat_formats {
-            for framesize in self.inner.enum_framesizes(format.fourcc)? {
-                // TODO: consider stepwise formats
-                if let v4l::framesize::FrameSizeEnum::Discrete(size) = framesize.size {
-                    for frameinterval in
-                        self.inner
-                            .enum_frameintervals(format.fourcc, size.width, size.height)?
-                    {
-                        // TODO: consider stepwise intervals
-                        if let v4l::frameinterval::FrameIntervalEnum::Discrete(fraction) =
-                            frameinterval.interval
-                        {
-                            streams.push(StreamDescriptor {
-                                width: size.width,
-                                height: size.height,
-                                pixfmt: PixelFormat::from(&format.fourcc.repr),
-                                interval: Duration::from_secs_f64(
-                                    fraction.numerator as f64 / fraction.denominator as f64,
-                                ),
-                            });
-                        }
+
+        for fourcc in self.fourccs()? {
+            for (width, height) in self.framesizes(fourcc)? {
+                // size ranges are only available through stream_ranges()
+                if width.min != width.max || height.min != height.max {
+                    continue;
+                }
+                let (width, height) = (width.min, height.min);
+
+                // sizes the driver has no interval information for are skipped
+                let intervals = match self.frameintervals(fourcc, width, height) {
+                    Ok(intervals) => intervals,
+                    Err(_) => continue,
+                };
+                for interval in intervals {
+                    let mut values = vec![interval.min];
+                    if interval.max != interval.min {
+                        values.push(interval.max);
+                    }
+
+                    for interval in values {
+                        streams.push(StreamDescriptor {
+                            width,
+                            height,
+                            pixfmt: PixelFormat::from(&fourcc.repr),
+                            interval,
+                        });
                     }
                 }
             }
@@ -84,124 +693,89 @@ impl<'a> Device<'a> for Handle {
         Ok(streams)
     }
 
-    fn controls(&self) -> Result<Vec<control::Descriptor>> {
-        let mut controls = Vec::new();
-        let plat_controls = self.inner.query_controls()?;
+    fn stream_ranges(&self) -> Result<Vec<StreamRangeDescriptor>> {
+        let mut streams = Vec::new();
 
-        for control in plat_controls {
-            // The v4l docs say applications should ignore permanently disabled controls.
-            if control.flags & v4l::control::Flags::DISABLED == v4l::control::Flags::DISABLED {
-                continue;
-            }
+        for fourcc in self.fourccs()? {
+            for (width, height) in self.framesizes(fourcc)? {
+                // Frame intervals may depend on the frame size. For size ranges, we report the
+                // intervals supported by both the smallest and the largest size. Sizes the driver
+                // has no interval information for are skipped.
+                let intervals = match (
+                    self.frameintervals(fourcc, width.min, height.min),
+                    self.frameintervals(fourcc, width.max, height.max),
+                ) {
+                    (Ok(min), Ok(max)) if min == max => max,
+                    (Ok(min), Ok(max)) => max
+                        .iter()
+                        .flat_map(|a| min.iter().filter_map(move |b| overlap(a, b)))
+                        .collect(),
+                    _ => continue,
+                };
 
-            let state_type = match control.typ {
-                ControlType::Integer | ControlType::Integer64 => control::Type::Number {
-                    range: (control.minimum as f64, control.maximum as f64),
-                    step: control.step as f32,
-                },
-                ControlType::Boolean => control::Type::Boolean,
-                ControlType::Menu => {
-                    let mut items = Vec::new();
-                    if let Some(plat_items) = control.items {
-                        for plat_item in plat_items {
-                            match plat_item.1 {
-                                ControlMenuItem::Name(name) => {
-                                    items.push(control::MenuItem::String(name));
-                                }
-                                ControlMenuItem::Value(value) => {
-                                    items.push(control::MenuItem::Number(value as f64));
-                                }
-                            }
-                        }
-                    }
-                    control::Type::Menu(items)
+                for interval in intervals {
+                    streams.push(StreamRangeDescriptor {
+                        width,
+                        height,
+                        pixfmt: PixelFormat::from(&fourcc.repr),
+                        interval,
+                    });
                 }
-                ControlType::Button => control::Type::Stateless,
-                ControlType::String => control::Type::String,
-                ControlType::Bitmask => control::Type::Bitmask,
-                _ => continue,
-            };
-
-            // assume controls to be readable and writable by default
-            let mut flags = control::Flags::READ | control::Flags::WRITE;
-
-            if control.flags & v4l::control::Flags::READ_ONLY == v4l::control::Flags::READ_ONLY {
-                flags.remove(control::Flags::WRITE);
-                flags.insert(control::Flags::READ);
-            }
-            if control.flags & v4l::control::Flags::WRITE_ONLY == v4l::control::Flags::WRITE_ONLY {
-                flags.remove(control::Flags::READ);
-                flags.insert(control::Flags::WRITE);
             }
-            if control.flags & v4l::control::Flags::GRABBED == v4l::control::Flags::GRABBED {
-                flags.remove(control::Flags::WRITE);
-            }
-            if control.flags & v4l::control::Flags::INACTIVE == v4l::control::Flags::INACTIVE {
-                flags.remove(control::Flags::READ);
-                flags.remove(control::Flags::WRITE);
-            }
-
-            controls.push(control::Descriptor {
-                id: control.id,
-                name: control.name,
-                typ: state_type,
-                flags,
-            })
         }
 
-        Ok(controls)
+        Ok(streams)
     }
 
-    fn control(&self, id: u32) -> Result<control::State> {
-        let ctrl = self.inner.control(id)?;
-        match ctrl.value {
-            ControlValue::Integer(val) => Ok(control::State::Number(val as f64)),
-            ControlValue::Boolean(val) => Ok(control::State::Boolean(val)),
-            _ => Err(Error::new(
-                ErrorKind::Other,
-                "control value cannot be mapped",
-            )),
-        }
+    fn controls(&self) -> Result<Vec<control::Descriptor>> {
+        let plat_controls = self.inner.query_controls().map_err(io_error)?;
+        let handle = self.inner.handle();
+
+        Ok(plat_controls
+            .into_iter()
+            .filter_map(|control| descriptor(&handle, control))
+            .collect())
     }
 
-    fn set_control(&mut self, id: u32, val: &control::State) -> Result<()> {
-        let value = match val {
-            control::State::Number(val) => ControlValue::Integer(*val as i64),
-            control::State::Boolean(val) => ControlValue::Boolean(*val),
-            _ => {
-                return Err(Error::new(
-                    ErrorKind::Other,
-                    "control value cannot be mapped",
-                ))
+    fn control_descriptor(&self, id: u32) -> Result<control::Descriptor> {
+        let handle = self.inner.handle();
+        let control = match v4l2_control::describe(&handle, id) {
+            Ok(control) => control,
+            Err(e) if e.kind() == io::ErrorKind::InvalidInput => {
+                return Err(Error::new(ErrorKind::NotFound, "unknown control ID"))
             }
+            Err(e) => return Err(io_error(e)),
         };
 
-        let ctrl = Control { id, value };
-        self.inner.set_control(ctrl)?;
+        descriptor(&handle, control)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "unknown control ID"))
+    }
 
-        Ok(())
+    fn find_control(&self, kind: control::Kind) -> Result<Option<control::Descriptor>> {
+        for id in v4l2_control::ids(kind) {
+            match self.control_descriptor(id) {
+                Ok(ctrl) => return Ok(Some(ctrl)),
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(None)
     }
 
-    fn start_stream(&self, desc: &StreamDescriptor) -> Result<Self::Stream> {
-        let fourcc = if let Ok(fourcc) = desc.pixfmt.clone().try_into() {
-            fourcc
-        } else {
-            return Err(Error::new(
-                ErrorKind::Other,
-                "failed to map pixelformat to fourcc",
-            ));
-        };
-        // configure frame format
-        let format = CaptureFormat::new(desc.width, desc.height, FourCC_::new(&fourcc));
-        self.inner.set_format(&format)?;
+    fn control(&self, id: u32) -> Result<control::State> {
+        v4l2_control::get(&self.inner.handle(), id)
+    }
 
-        // configure frame timing
-        let fps = (1.0 / desc.interval.as_secs_f32()) as u32;
-        let mut params = self.inner.params()?;
-        params.interval = v4l::Fraction::new(1, fps);
-        self.inner.set_params(&params)?;
+    fn set_control(&mut self, id: u32, val: &control::State) -> Result<()> {
+        v4l2_control::set(&self.inner.handle(), id, val)
+    }
 
-        let handle = StreamHandle::new(self)?;
-        Ok(handle)
+    fn set_controls(&mut self, ctrls: &[(u32, control::State)]) -> Result<()> {
+        v4l2_control::set_all(&self.inner.handle(), ctrls)
+    }
+
+    fn start_stream(&self, desc: &StreamDescriptor) -> Result<Self::Stream> {
+        self.start_stream_with(desc, &StreamOptions::default())
     }
 }
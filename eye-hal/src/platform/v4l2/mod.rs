@@ -5,13 +5,53 @@
 //! # Related Links
 //! * <https://linuxtv.org/downloads/v4l-dvb-apis-new/userspace-api/v4l/v4l2.html> - Video for Linux API
 
+mod arena;
 pub mod context;
+pub mod control;
 pub mod device;
+pub mod monitor;
+pub mod mplane;
 pub mod stream;
 
-use std::{convert::TryInto, str};
+use std::{convert::TryInto, io, str};
 
+use crate::error::{Error, ErrorKind};
 use crate::format::PixelFormat;
+use crate::stream::Fraction;
+
+/// Converts an I/O error returned by a V4L2 syscall.
+///
+/// The errno values carry V4L2 specific meanings which are more precise than the generic
+/// [`io::ErrorKind`], so those are mapped first.
+pub(crate) fn io_error(error: io::Error) -> Error {
+    match error.raw_os_error().and_then(error_kind) {
+        Some(kind) => Error::new(kind, error),
+        None => Error::from(error),
+    }
+}
+
+/// Maps an errno value as set by the V4L2 syscalls to an error kind.
+///
+/// Returns `None` for codes which do not map to any specific kind.
+fn error_kind(errno: i32) -> Option<ErrorKind> {
+    match errno {
+        libc::EBUSY => Some(ErrorKind::Busy),
+        libc::EACCES | libc::EPERM => Some(ErrorKind::PermissionDenied),
+        libc::ENOENT => Some(ErrorKind::NotFound),
+        // the kernel returns ENODEV for all operations once the device has been unplugged
+        libc::ENODEV => Some(ErrorKind::Disconnected),
+        libc::ETIMEDOUT => Some(ErrorKind::Timeout),
+        libc::EINVAL | libc::ERANGE => Some(ErrorKind::InvalidArgument),
+        libc::ENOTTY | libc::EOPNOTSUPP => Some(ErrorKind::NotSupported),
+        _ => None,
+    }
+}
+
+impl From<v4l::Fraction> for Fraction {
+    fn from(fraction: v4l::Fraction) -> Self {
+        Fraction::new(fraction.numerator, fraction.denominator)
+    }
+}
 
 impl From<&[u8; 4]> for PixelFormat {
     fn from(fourcc: &[u8; 4]) -> Self {
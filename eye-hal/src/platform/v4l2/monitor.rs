@@ -0,0 +1,211 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::ffi::CStr;
+use std::os::unix::io::RawFd;
+use std::time::{Duration, Instant};
+use std::{io, mem, ptr};
+
+use v4l::context;
+
+use crate::device;
+use crate::error::{Error, ErrorKind, Result};
+use crate::platform::v4l2::context::describe;
+
+/// Hotplug monitor
+///
+/// Watches /dev for video nodes being created and removed using inotify. Nodes are only reported
+/// once they can actually be opened, i.e. udev has finished setting up their permissions.
+pub struct Monitor {
+    fd: RawFd,
+    // known devices by node index
+    known: HashMap<usize, device::Description>,
+    pending: VecDeque<device::Event>,
+    buf: Vec<u8>,
+}
+
+impl Monitor {
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut monitor = Monitor {
+            fd,
+            known: HashMap::new(),
+            pending: VecDeque::new(),
+            buf: vec![0; 4096],
+        };
+
+        let mask = libc::IN_CREATE
+            | libc::IN_DELETE
+            | libc::IN_ATTRIB
+            | libc::IN_MOVED_FROM
+            | libc::IN_MOVED_TO;
+        let path = CStr::from_bytes_with_nul(b"/dev\0").unwrap();
+        if unsafe { libc::inotify_add_watch(fd, path.as_ptr(), mask) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // The watch is in place, so we will not miss any node created while scanning.
+        monitor.known = Self::scan();
+        Ok(monitor)
+    }
+
+    fn scan() -> HashMap<usize, device::Description> {
+        context::enum_devices()
+            .into_iter()
+            .filter_map(|dev| Some((dev.index(), describe(dev.index())?)))
+            .collect()
+    }
+
+    fn rescan(&mut self) {
+        let known = Self::scan();
+        for (index, desc) in &self.known {
+            if !known.contains_key(index) {
+                self.pending.push_back(device::Event::Removed(desc.clone()));
+            }
+        }
+        for (index, desc) in &known {
+            if !self.known.contains_key(index) {
+                self.pending.push_back(device::Event::Added(desc.clone()));
+            }
+        }
+        self.known = known;
+    }
+
+    fn handle(&mut self, mask: u32, name: &[u8]) {
+        let index = match name
+            .strip_prefix(b"video")
+            .and_then(|index| std::str::from_utf8(index).ok())
+            .and_then(|index| index.parse::<usize>().ok())
+        {
+            Some(index) => index,
+            None => return,
+        };
+
+        if mask & (libc::IN_DELETE | libc::IN_MOVED_FROM) != 0 {
+            if let Some(desc) = self.known.remove(&index) {
+                self.pending.push_back(device::Event::Removed(desc));
+            }
+        } else if let Entry::Vacant(entry) = self.known.entry(index) {
+            // Freshly created nodes may not be accessible until udev has adjusted their
+            // permissions, in which case we try again on the following IN_ATTRIB event.
+            if let Some(desc) = describe(index) {
+                entry.insert(desc.clone());
+                self.pending.push_back(device::Event::Added(desc));
+            }
+        }
+    }
+
+    fn read(&mut self) -> io::Result<()> {
+        let len = unsafe {
+            libc::read(
+                self.fd,
+                self.buf.as_mut_ptr() as *mut std::os::raw::c_void,
+                self.buf.len(),
+            )
+        };
+        if len < 0 {
+            let err = io::Error::last_os_error();
+            return match err.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => Ok(()),
+                _ => Err(err),
+            };
+        }
+
+        let buf = mem::take(&mut self.buf);
+        let mut offset = 0;
+        while offset + mem::size_of::<libc::inotify_event>() <= len as usize {
+            let event = unsafe {
+                ptr::read_unaligned(buf[offset..].as_ptr() as *const libc::inotify_event)
+            };
+            let name_start = offset + mem::size_of::<libc::inotify_event>();
+            let name_end = name_start + event.len as usize;
+            offset = name_end;
+
+            if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                // we lost track, so compare against a full scan
+                self.rescan();
+                continue;
+            }
+
+            // the name is padded with NUL bytes
+            let name = &buf[name_start..name_end];
+            let name = match name.iter().position(|&b| b == 0) {
+                Some(end) => &name[..end],
+                None => name,
+            };
+            self.handle(event.mask, name);
+        }
+        self.buf = buf;
+
+        Ok(())
+    }
+
+    /// Returns the next event, waiting at most `timeout` for it
+    ///
+    /// Returns an error of kind [`ErrorKind::Timeout`] if nothing happened in time.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<Result<device::Event>> {
+        self.wait(Some(timeout))
+    }
+
+    fn wait(&mut self, timeout: Option<Duration>) -> Option<Result<device::Event>> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(Ok(event));
+            }
+
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    i32::try_from(remaining.as_millis()).unwrap_or(i32::MAX)
+                }
+                None => -1,
+            };
+            let mut pollfd = libc::pollfd {
+                fd: self.fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            match unsafe { libc::poll(&mut pollfd, 1, timeout) } {
+                -1 => {
+                    let err = io::Error::last_os_error();
+                    if err.kind() != io::ErrorKind::Interrupted {
+                        return Some(Err(err.into()));
+                    }
+                }
+                0 => {
+                    return Some(Err(Error::new(
+                        ErrorKind::Timeout,
+                        "timed out waiting for a hotplug event",
+                    )))
+                }
+                _ => {
+                    if let Err(e) = self.read() {
+                        return Some(Err(e.into()));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for Monitor {
+    type Item = Result<device::Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.wait(None)
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! Multi-planar capture
+//!
+//! Devices exposing the multi-planar API use a distinct buffer type. The v4l crate only covers
+//! the single-planar API, so we issue the format and parameter ioctls ourselves.
+
+use std::{io, mem};
+
+use v4l::buffer::Type as BufType;
+use v4l::device::Handle;
+use v4l::v4l2;
+use v4l::v4l_sys::*;
+use v4l::video::capture::Parameters;
+use v4l::FourCC;
+
+/// Multi-planar frame format
+pub struct Format {
+    /// Width in pixels
+    pub width: u32,
+    /// Height in pixels
+    pub height: u32,
+    /// Pixelformat code
+    pub fourcc: FourCC,
+    /// Row length and maximum size in bytes for each memory plane
+    pub planes: Vec<(u32, u32)>,
+}
+
+impl From<v4l2_pix_format_mplane> for Format {
+    fn from(fmt: v4l2_pix_format_mplane) -> Self {
+        // the struct is packed, so copy everything out before touching it
+        let plane_fmt = fmt.plane_fmt;
+        let planes = plane_fmt
+            .iter()
+            .take(fmt.num_planes as usize)
+            .map(|plane| (plane.bytesperline, plane.sizeimage))
+            .collect();
+
+        Format {
+            width: fmt.width,
+            height: fmt.height,
+            fourcc: FourCC::from(fmt.pixelformat),
+            planes,
+        }
+    }
+}
+
+/// Returns the pixelformats supported by the device
+///
+/// # Arguments
+///
+/// * `handle` - Device handle
+pub fn enum_formats(handle: &Handle) -> io::Result<Vec<FourCC>> {
+    let mut fourccs = Vec::new();
+    let mut v4l2_fmt = v4l2_fmtdesc {
+        index: 0,
+        type_: BufType::VideoCaptureMplane as u32,
+        ..unsafe { mem::zeroed() }
+    };
+
+    // the driver signals the end of the list with EINVAL
+    while unsafe {
+        v4l2::ioctl(
+            handle.fd(),
+            v4l2::vidioc::VIDIOC_ENUM_FMT,
+            &mut v4l2_fmt as *mut _ as *mut std::os::raw::c_void,
+        )
+    }
+    .is_ok()
+    {
+        fourccs.push(FourCC::from(v4l2_fmt.pixelformat));
+        v4l2_fmt.index += 1;
+    }
+
+    Ok(fourccs)
+}
+
+/// Returns the current format
+///
+/// # Arguments
+///
+/// * `handle` - Device handle
+pub fn format(handle: &Handle) -> io::Result<Format> {
+    let mut v4l2_fmt = v4l2_format {
+        type_: BufType::VideoCaptureMplane as u32,
+        ..unsafe { mem::zeroed() }
+    };
+    unsafe {
+        v4l2::ioctl(
+            handle.fd(),
+            v4l2::vidioc::VIDIOC_G_FMT,
+            &mut v4l2_fmt as *mut _ as *mut std::os::raw::c_void,
+        )?;
+
+        Ok(Format::from(v4l2_fmt.fmt.pix_mp))
+    }
+}
+
+/// Sets the format, returns the one picked by the driver
+///
+/// # Arguments
+///
+/// * `handle` - Device handle
+/// * `width` - Width in pixels
+/// * `height` - Height in pixels
+/// * `fourcc` - Pixelformat code
+pub fn set_format(handle: &Handle, width: u32, height: u32, fourcc: FourCC) -> io::Result<Format> {
+    request_format(handle, v4l2::vidioc::VIDIOC_S_FMT, width, height, fourcc)
+}
+
+/// Returns the format the driver would pick, without changing the device
+///
+/// # Arguments
+///
+/// * `handle` - Device handle
+/// * `width` - Width in pixels
+/// * `height` - Height in pixels
+/// * `fourcc` - Pixelformat code
+pub fn try_format(handle: &Handle, width: u32, height: u32, fourcc: FourCC) -> io::Result<Format> {
+    request_format(handle, v4l2::vidioc::VIDIOC_TRY_FMT, width, height, fourcc)
+}
+
+fn request_format(
+    handle: &Handle,
+    request: v4l2::vidioc::_IOC_TYPE,
+    width: u32,
+    height: u32,
+    fourcc: FourCC,
+) -> io::Result<Format> {
+    let mut v4l2_fmt = v4l2_format {
+        type_: BufType::VideoCaptureMplane as u32,
+        ..unsafe { mem::zeroed() }
+    };
+    unsafe {
+        // let the driver fill in the plane layout
+        v4l2_fmt.fmt.pix_mp.width = width;
+        v4l2_fmt.fmt.pix_mp.height = height;
+        v4l2_fmt.fmt.pix_mp.pixelformat = fourcc.into();
+        v4l2::ioctl(
+            handle.fd(),
+            request,
+            &mut v4l2_fmt as *mut _ as *mut std::os::raw::c_void,
+        )?;
+
+        Ok(Format::from(v4l2_fmt.fmt.pix_mp))
+    }
+}
+
+/// Returns the current streaming parameters
+///
+/// # Arguments
+///
+/// * `handle` - Device handle
+pub fn params(handle: &Handle) -> io::Result<Parameters> {
+    let mut v4l2_params = v4l2_streamparm {
+        type_: BufType::VideoCaptureMplane as u32,
+        ..unsafe { mem::zeroed() }
+    };
+    unsafe {
+        v4l2::ioctl(
+            handle.fd(),
+            v4l2::vidioc::VIDIOC_G_PARM,
+            &mut v4l2_params as *mut _ as *mut std::os::raw::c_void,
+        )?;
+
+        Ok(Parameters::from(v4l2_params.parm.capture))
+    }
+}
+
+/// Sets the streaming parameters, returns the ones picked by the driver
+///
+/// # Arguments
+///
+/// * `handle` - Device handle
+/// * `params` - Streaming parameters
+pub fn set_params(handle: &Handle, params: &Parameters) -> io::Result<Parameters> {
+    let mut v4l2_params = v4l2_streamparm {
+        type_: BufType::VideoCaptureMplane as u32,
+        parm: v4l2_streamparm__bindgen_ty_1 {
+            capture: (*params).into(),
+        },
+    };
+    unsafe {
+        v4l2::ioctl(
+            handle.fd(),
+            v4l2::vidioc::VIDIOC_S_PARM,
+            &mut v4l2_params as *mut _ as *mut std::os::raw::c_void,
+        )?;
+    }
+
+    self::params(handle)
+}
@@ -1,47 +1,431 @@
+use std::io;
+use std::os::fd::OwnedFd;
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "async")]
+use std::{
+    os::fd::{AsFd, BorrowedFd},
+    task::{Context, Poll},
+};
+
+#[cfg(feature = "async")]
+use async_io::Async;
 use v4l::buffer::Type as BufType;
-use v4l::io::mmap::Stream as MmapStream;
-use v4l::io::traits::{CaptureStream, Stream as _};
+use v4l::capability::Flags as CapFlags;
+use v4l::v4l2;
 
-use crate::error::Result;
+use crate::error::{Error, ErrorKind, Result};
+use crate::format::ImageFormat;
+#[cfg(feature = "async")]
+use crate::platform::v4l2::arena::exhausted;
+use crate::platform::v4l2::arena::{Aligned, Arena, Lease};
 use crate::platform::v4l2::device::Handle as DeviceHandle;
+use crate::platform::v4l2::io_error;
+use crate::platform::v4l2::mplane;
+use crate::stream::{Buffers, Fraction, Frame, Io, Metadata, Options, OwnedFrame, Plane, Policy};
 use crate::traits::Stream;
 
-pub struct Handle<'a> {
-    stream: MmapStream<'a>,
+pub struct Handle {
+    arena: Arc<Arena>,
+    format: ImageFormat,
+    interval: Fraction,
+    policy: Policy,
+    // row length of each memory plane, empty for single-planar streams
+    strides: Vec<usize>,
+    // buffer lent out by the last call to next()
+    current: Option<usize>,
     active: bool,
+    // readiness of the device fd, registered on first use
+    #[cfg(feature = "async")]
+    fd: Option<Async<DeviceFd>>,
 }
 
-impl<'a> Handle<'a> {
-    pub fn new(dev: &DeviceHandle) -> Result<Self> {
-        let stream = MmapStream::new(dev.inner(), BufType::VideoCapture)?;
-        Ok(Handle {
-            stream,
+// number of buffers shared with the driver unless requested otherwise
+const BUFFERS: u32 = 4;
+
+impl Handle {
+    /// Returns a stream using buffers allocated by us or the driver
+    ///
+    /// # Arguments
+    ///
+    /// * `dev` - Device handle
+    /// * `format` - Negotiated image format
+    /// * `interval` - Negotiated frame interval
+    /// * `opts` - Stream options
+    pub fn new(
+        dev: &DeviceHandle,
+        format: ImageFormat,
+        interval: Fraction,
+        opts: &Options,
+    ) -> Result<Self> {
+        if dev.buf_type() as u32 == BufType::VideoCaptureMplane as u32 {
+            return Self::mplane(dev, format, interval, opts);
+        }
+
+        let handle = dev.inner().handle();
+        let count = opts.buffers.unwrap_or(BUFFERS);
+        // buffers allocated by us must be able to hold a complete frame
+        let frame_size = || match format.size {
+            Some(size) if size > 0 => Ok(size),
+            _ => Err(Error::new(
+                ErrorKind::InvalidArgument,
+                "driver did not report the frame size",
+            )),
+        };
+
+        let arena = match opts.io {
+            Io::Mmap => Arena::new(handle, BufType::VideoCapture, count).map_err(io_error)?,
+            Io::UserPtr => Self::userptr(handle, frame_size()?, count).map_err(io_error)?,
+            Io::Read => Arena::with_read(handle, frame_size()?, count).map_err(io_error)?,
+            Io::DmaBuf => {
+                return Err(Error::new(
+                    ErrorKind::InvalidArgument,
+                    "DMABUF streaming requires imported buffers",
+                ))
+            }
+            Io::Auto => {
+                let caps = dev.inner().query_caps().map_err(io_error)?.capabilities;
+                let streaming = caps.contains(CapFlags::STREAMING);
+                let read = caps.contains(CapFlags::READ_WRITE);
+                if !streaming && read {
+                    Arena::with_read(handle, frame_size()?, count).map_err(io_error)?
+                } else {
+                    // not all drivers can map their memory, try user pointers and read() calls
+                    // before giving up
+                    match Arena::new(handle.clone(), BufType::VideoCapture, count) {
+                        Ok(arena) => arena,
+                        Err(e) => match frame_size().and_then(|size| {
+                            Self::userptr(handle.clone(), size, count).map_err(io_error)
+                        }) {
+                            Ok(arena) => arena,
+                            Err(_) if read => {
+                                Arena::with_read(handle, frame_size()?, count).map_err(io_error)?
+                            }
+                            Err(_) => return Err(io_error(e)),
+                        },
+                    }
+                }
+            }
+        };
+
+        Ok(Self::from_arena(arena, format, interval, opts.policy))
+    }
+
+    /// Returns a multi-planar stream, only buffers allocated by the driver are supported
+    fn mplane(
+        dev: &DeviceHandle,
+        format: ImageFormat,
+        interval: Fraction,
+        opts: &Options,
+    ) -> Result<Self> {
+        if opts.io != Io::Auto && opts.io != Io::Mmap {
+            return Err(Error::new(
+                ErrorKind::NotSupported,
+                "multi-planar streams only support mmap I/O",
+            ));
+        }
+
+        let handle = dev.inner().handle();
+        let strides = mplane::format(&handle)
+            .map_err(io_error)?
+            .planes
+            .iter()
+            .map(|plane| plane.0 as usize)
+            .collect();
+        let count = opts.buffers.unwrap_or(BUFFERS);
+        let arena = Arena::new(handle, BufType::VideoCaptureMplane, count).map_err(io_error)?;
+
+        let mut stream = Self::from_arena(arena, format, interval, opts.policy);
+        stream.strides = strides;
+        Ok(stream)
+    }
+
+    /// Returns a stream capturing into buffers supplied by the caller
+    ///
+    /// # Arguments
+    ///
+    /// * `dev` - Device handle
+    /// * `format` - Negotiated image format
+    /// * `interval` - Negotiated frame interval
+    /// * `bufs` - Buffers to capture into
+    /// * `policy` - Queue policy
+    pub fn with_buffers(
+        dev: &DeviceHandle,
+        format: ImageFormat,
+        interval: Fraction,
+        bufs: Buffers,
+        policy: Policy,
+    ) -> Result<Self> {
+        if dev.buf_type() as u32 == BufType::VideoCaptureMplane as u32 {
+            return Err(Error::new(
+                ErrorKind::NotSupported,
+                "multi-planar streams cannot capture into supplied buffers",
+            ));
+        }
+
+        let handle = dev.inner().handle();
+        let arena = match bufs {
+            Buffers::UserPtr(bufs) if !bufs.is_empty() => {
+                Arena::with_userptr(handle, BufType::VideoCapture, bufs).map_err(io_error)?
+            }
+            Buffers::DmaBuf(fds) if !fds.is_empty() => {
+                Arena::with_dmabuf(handle, BufType::VideoCapture, fds).map_err(io_error)?
+            }
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidArgument,
+                    "no buffers supplied",
+                ))
+            }
+        };
+
+        Ok(Self::from_arena(arena, format, interval, policy))
+    }
+
+    fn userptr(handle: Arc<v4l::device::Handle>, size: usize, count: u32) -> io::Result<Arena> {
+        let bufs = (0..count)
+            .map(|_| Ok(Box::new(Aligned::new(size)?) as Box<dyn AsMut<[u8]> + Send>))
+            .collect::<io::Result<Vec<_>>>()?;
+        Arena::with_userptr(handle, BufType::VideoCapture, bufs)
+    }
+
+    fn from_arena(arena: Arena, format: ImageFormat, interval: Fraction, policy: Policy) -> Self {
+        Handle {
+            arena: Arc::new(arena),
+            format,
+            interval,
+            policy,
+            strides: Vec::new(),
+            current: None,
             active: false,
-        })
+            #[cfg(feature = "async")]
+            fd: None,
+        }
+    }
+
+    /// Returns the number of buffers granted by the driver
+    pub fn buffers(&self) -> usize {
+        self.arena.len()
+    }
+
+    /// Exports the stream buffers as DMABUF file descriptors
+    ///
+    /// The descriptors are ordered by buffer index, see [`crate::stream::Metadata::buffer`].
+    /// Only buffers allocated by the driver can be exported.
+    pub fn export(&self) -> Result<Vec<OwnedFd>> {
+        self.arena.export().map_err(io_error)
+    }
+
+    /// Returns the image format negotiated with the driver
+    pub fn format(&self) -> &ImageFormat {
+        &self.format
+    }
+
+    /// Returns the frame interval negotiated with the driver
+    pub fn interval(&self) -> Fraction {
+        self.interval
     }
 
-    fn _start(&mut self) -> Result<()> {
+    fn start(&mut self) -> Result<()> {
         if self.active {
             return Ok(());
         }
 
-        self.stream.start()?;
+        for index in 0..self.arena.len() {
+            self.arena.queue(index).map_err(io_error)?;
+        }
+
+        // capturing starts with the first read() call
+        if self.arena.io() == Io::Read {
+            self.active = true;
+            return Ok(());
+        }
+
+        let mut typ = self.arena.buf_type() as u32;
+        unsafe {
+            v4l2::ioctl(
+                self.arena.handle().fd(),
+                v4l2::vidioc::VIDIOC_STREAMON,
+                &mut typ as *mut _ as *mut std::os::raw::c_void,
+            )
+            .map_err(io_error)?;
+        }
+
         self.active = true;
         Ok(())
     }
 
     fn stop(&mut self) -> Result<()> {
-        if !self.active {
+        if !self.active || self.arena.io() == Io::Read {
+            self.active = false;
             return Ok(());
         }
 
-        self.stream.stop()?;
+        let mut typ = self.arena.buf_type() as u32;
+        unsafe {
+            v4l2::ioctl(
+                self.arena.handle().fd(),
+                v4l2::vidioc::VIDIOC_STREAMOFF,
+                &mut typ as *mut _ as *mut std::os::raw::c_void,
+            )
+            .map_err(io_error)?;
+        }
+
         self.active = false;
         Ok(())
     }
+
+    fn prepare(&mut self) -> Result<()> {
+        if !self.active {
+            self.start()?;
+        } else if let Some(index) = self.current.take() {
+            self.arena.queue(index).map_err(io_error)?;
+        }
+
+        Ok(())
+    }
+
+    fn dequeue(&mut self, timeout: Option<Duration>) -> Result<(usize, Metadata)> {
+        self.prepare()?;
+        let item = self.arena.dequeue(timeout).map_err(io_error)?;
+        self.apply_policy(item)
+    }
+
+    /// Hands all but the most recent buffer back to the driver if old frames are to be dropped
+    fn apply_policy(&self, mut item: (usize, Metadata)) -> Result<(usize, Metadata)> {
+        if self.policy == Policy::DropOld {
+            // errors surface on the next call, we still have a frame to return
+            while let Ok(newer) = self.arena.try_dequeue() {
+                self.arena.queue(item.0).map_err(io_error)?;
+                item = newer;
+            }
+        }
+
+        Ok(item)
+    }
+
+    /// Returns the plane layout of a dequeued buffer
+    fn planes(&self, index: usize) -> Vec<Plane> {
+        self.arena
+            .layout(index)
+            .into_iter()
+            .zip(&self.strides)
+            .map(|((offset, len), stride)| Plane {
+                offset,
+                len,
+                stride: *stride,
+            })
+            .collect()
+    }
+
+    fn frame(&mut self, timeout: Option<Duration>) -> Option<Result<Frame<'_>>> {
+        match self.dequeue(timeout) {
+            Ok((index, meta)) => {
+                self.current = Some(index);
+                let planes = self.planes(index);
+                Some(Ok(Frame::new(self.arena.buf(index, meta.bytesused))
+                    .meta(meta)
+                    .planes(planes)))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+
+    /// Advances the stream, waiting at most `timeout` for the next frame
+    ///
+    /// Returns an error of kind [`crate::ErrorKind::Timeout`] if no frame arrived in time.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<Result<Frame<'_>>> {
+        self.frame(Some(timeout))
+    }
+
+    /// Returns the next frame if one is ready, without waiting
+    ///
+    /// Returns an error of kind [`crate::ErrorKind::Timeout`] if no frame is ready.
+    pub fn try_next(&mut self) -> Option<Result<Frame<'_>>> {
+        self.frame(Some(Duration::ZERO))
+    }
+
+    fn lease(&self, index: usize, meta: Metadata) -> OwnedFrame {
+        let lease = Lease::new(self.arena.clone(), index, meta.bytesused);
+        OwnedFrame::new(lease).meta(meta).planes(self.planes(index))
+    }
+
+    /// Advances the stream and returns the next frame as an owned buffer
+    ///
+    /// The frame is backed by one of the stream buffers, which is handed back to the driver once
+    /// the frame is dropped. No data is copied, except for multi-planar frames spanning several
+    /// memory planes: those are gathered in a staging buffer, see [`OwnedFrame::plane`].
+    ///
+    /// If all buffers are lent out, this blocks until an owned frame is dropped.
+    pub fn next_owned(&mut self) -> Option<Result<OwnedFrame>> {
+        match self.dequeue(None) {
+            Ok((index, meta)) => Some(Ok(self.lease(index, meta))),
+            Err(e) => Some(Err(e)),
+        }
+    }
+
+    /// Attempts to pull out the next frame, registering the current task for wakeup if no frame
+    /// is available yet
+    ///
+    /// The device fd is registered with the [`async_io`] reactor (epoll on Linux), so waiting
+    /// for a frame does not block the calling thread. If all buffers are lent out, the task is
+    /// woken up once an owned frame is dropped.
+    #[cfg(feature = "async")]
+    pub fn poll_next_owned(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<OwnedFrame>>> {
+        if let Err(e) = self.prepare() {
+            return Poll::Ready(Some(Err(e)));
+        }
+
+        if self.fd.is_none() {
+            match Async::new(DeviceFd(self.arena.handle().clone())) {
+                Ok(fd) => self.fd = Some(fd),
+                Err(e) => return Poll::Ready(Some(Err(io_error(e)))),
+            }
+        }
+
+        loop {
+            match self.arena.try_dequeue() {
+                Ok(item) => {
+                    let (index, meta) = match self.apply_policy(item) {
+                        Ok(item) => item,
+                        Err(e) => return Poll::Ready(Some(Err(e))),
+                    };
+                    return Poll::Ready(Some(Ok(self.lease(index, meta))));
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                // every buffer is lent out, wait for an owned frame to be dropped
+                Err(e) if exhausted(&e) => {
+                    if self.arena.register(cx.waker()) {
+                        return Poll::Pending;
+                    }
+                    continue;
+                }
+                Err(e) => return Poll::Ready(Some(Err(io_error(e)))),
+            }
+
+            let fd = self.fd.as_ref().unwrap();
+            match fd.poll_readable(cx) {
+                Poll::Ready(Ok(())) => continue,
+                Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(io_error(e)))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
 }
 
-impl<'a> Drop for Handle<'a> {
+#[cfg(feature = "async")]
+/// Device fd as seen by the async reactor
+struct DeviceFd(Arc<v4l::device::Handle>);
+
+#[cfg(feature = "async")]
+impl AsFd for DeviceFd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        // the handle keeps the fd open for as long as we are alive
+        unsafe { BorrowedFd::borrow_raw(self.0.fd()) }
+    }
+}
+
+impl Drop for Handle {
     fn drop(&mut self) {
         if self.active {
             // ignore the result
@@ -50,18 +434,10 @@ impl<'a> Drop for Handle<'a> {
     }
 }
 
-impl<'a, 'b> Stream<'b> for Handle<'a> {
-    type Item = Result<&'b [u8]>;
+impl<'a> Stream<'a> for Handle {
+    type Item = Result<Frame<'a>>;
 
-    fn next(&'b mut self) -> Option<Self::Item> {
-        match self.stream.next() {
-            Ok((buf, meta)) => {
-                // For compressed formats, the buffer length will not actually describe the number
-                // of bytes in a frame. Instead, we have to explicitly query about the amount of
-                // used bytes.
-                Some(Ok(&buf[0..meta.bytesused as usize]))
-            }
-            Err(e) => Some(Err(e.into())),
-        }
+    fn next(&'a mut self) -> Option<Self::Item> {
+        self.frame(None)
     }
 }
@@ -0,0 +1,150 @@
+//! Control profiles
+//!
+//! A profile is a snapshot of the control values of a device which can be applied again later,
+//! e.g. to restore the camera settings after a reboot. With the `toml` or `json` features
+//! enabled, profiles can be stored as text.
+
+use crate::control;
+#[cfg(any(feature = "toml", feature = "json"))]
+use crate::error::ErrorKind;
+use crate::error::{Error, Result};
+use crate::traits::Device;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Control profile
+pub struct Profile {
+    /// Control values
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Control profile entry
+pub struct Entry {
+    /// Control identifier
+    pub id: u32,
+    /// Control name, for informational purposes only
+    pub name: String,
+    /// Well-known meaning of the control, if any
+    pub kind: Option<control::Kind>,
+    /// Control value
+    pub value: control::State,
+}
+
+impl Entry {
+    /// Returns true if the control switches an automatic mode
+    ///
+    /// Automatic modes decide whether the related manual controls can be written, so they have to
+    /// be applied first. Only well-known controls are recognized, names are not reliable enough.
+    pub fn is_auto(&self) -> bool {
+        matches!(
+            self.kind,
+            Some(control::Kind::AutoExposure)
+                | Some(control::Kind::AutoFocus)
+                | Some(control::Kind::AutoGain)
+                | Some(control::Kind::AutoWhiteBalance)
+        )
+    }
+}
+
+#[derive(Debug)]
+/// Control which could not be applied
+pub struct Failure {
+    /// Control identifier
+    pub id: u32,
+    /// Control name
+    pub name: String,
+    /// Reason
+    pub error: Error,
+}
+
+impl Profile {
+    /// Takes a snapshot of all controls which are both readable and writable
+    ///
+    /// Inactive controls are included, e.g. the manual exposure time while automatic exposure is
+    /// on. Their values take effect once the automatic mode is turned off.
+    ///
+    /// # Arguments
+    ///
+    /// * `dev` - Device to read the controls from
+    pub fn capture<'a, D: Device<'a> + ?Sized>(dev: &D) -> Result<Self> {
+        let mut entries = Vec::new();
+        for ctrl in dev.controls()? {
+            if !ctrl.readable() || !ctrl.writable() {
+                continue;
+            }
+            if let control::Type::Stateless = ctrl.typ {
+                continue;
+            }
+
+            // skip controls which cannot be read at the moment
+            let value = match dev.control(ctrl.id) {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+
+            entries.push(Entry {
+                id: ctrl.id,
+                name: ctrl.name,
+                kind: ctrl.kind,
+                value,
+            });
+        }
+
+        Ok(Profile { entries })
+    }
+
+    /// Applies the profile to a device
+    ///
+    /// Automatic modes are applied before all other controls, so manual values which are inactive
+    /// in the profile are written after their automatic mode. Failing controls are skipped and
+    /// returned once all entries have been processed, so an empty list means success.
+    ///
+    /// # Arguments
+    ///
+    /// * `dev` - Device to write the controls to
+    pub fn apply<'a, D: Device<'a> + ?Sized>(&self, dev: &mut D) -> Vec<Failure> {
+        let (auto, manual): (Vec<&Entry>, Vec<&Entry>) =
+            self.entries.iter().partition(|entry| entry.is_auto());
+
+        let mut failures = Vec::new();
+        for entry in auto.into_iter().chain(manual) {
+            if let Err(error) = dev.set_control(entry.id, &entry.value) {
+                failures.push(Failure {
+                    id: entry.id,
+                    name: entry.name.clone(),
+                    error,
+                });
+            }
+        }
+
+        failures
+    }
+}
+
+#[cfg(feature = "toml")]
+impl Profile {
+    /// Serializes the profile as TOML document
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).map_err(|e| Error::new(ErrorKind::Other, e))
+    }
+
+    /// Deserializes a profile from a TOML document
+    pub fn from_toml(s: &str) -> Result<Self> {
+        toml::from_str(s).map_err(|e| Error::new(ErrorKind::Other, e))
+    }
+}
+
+#[cfg(feature = "json")]
+impl Profile {
+    /// Serializes the profile as JSON document
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).map_err(|e| Error::new(ErrorKind::Other, e))
+    }
+
+    /// Deserializes a profile from a JSON document
+    pub fn from_json(s: &str) -> Result<Self> {
+        serde_json::from_str(s).map_err(|e| Error::new(ErrorKind::Other, e))
+    }
+}
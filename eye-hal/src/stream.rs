@@ -1,5 +1,9 @@
-use std::time;
+use std::cmp::Ordering;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::{fmt, mem, time};
 
+use crate::error::{Error, ErrorKind, Result};
 use crate::format::PixelFormat;
 
 #[derive(Clone, Debug)]
@@ -11,6 +15,753 @@ pub struct Descriptor {
     pub height: u32,
     /// PixelFormat
     pub pixfmt: PixelFormat,
-    /// Frame timing as duration
-    pub interval: time::Duration,
+    /// Frame interval in seconds
+    pub interval: Fraction,
+}
+
+impl Descriptor {
+    /// Returns the frame interval as duration
+    pub fn interval_duration(&self) -> time::Duration {
+        self.interval.as_duration()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+/// Stream options
+///
+/// Used to tune how a stream is set up by the backend, see
+/// [`crate::platform::Device::start_stream_with`].
+pub struct Options {
+    /// Fail instead of silently accepting a format adjusted by the driver
+    ///
+    /// Drivers are free to adjust the requested frame size, pixelformat and interval to the
+    /// closest values they support.
+    pub strict: bool,
+    /// How frames are transferred from the driver
+    pub io: Io,
+    /// Number of buffers to allocate, the backend picks a default if unset
+    ///
+    /// More buffers make the stream more robust against hiccups in frame processing, at the cost
+    /// of latency when frames pile up. The number actually granted by the backend is reported
+    /// by [`crate::platform::Stream::buffers`].
+    pub buffers: Option<u32>,
+    /// What happens to frames which pile up while the application is busy
+    pub policy: Policy,
+}
+
+impl Options {
+    /// Builder pattern constructor
+    ///
+    /// # Arguments
+    ///
+    /// * `strict` - Whether to fail if the driver adjusts the stream format
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Builder pattern constructor
+    ///
+    /// # Arguments
+    ///
+    /// * `io` - I/O method
+    pub fn io(mut self, io: Io) -> Self {
+        self.io = io;
+        self
+    }
+
+    /// Builder pattern constructor
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - Number of buffers
+    pub fn buffers(mut self, count: u32) -> Self {
+        self.buffers = Some(count);
+        self
+    }
+
+    /// Builder pattern constructor
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - Queue policy
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// Queue policy
+///
+/// Decides which frame is returned when more than one was captured since the stream was last
+/// advanced.
+pub enum Policy {
+    /// Return every frame in capture order
+    #[default]
+    QueueAll,
+    /// Return the most recent frame and drop the older ones, trading completeness for latency
+    DropOld,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// Streaming I/O method
+///
+/// Not all backends support all methods, they fail with [`ErrorKind::NotSupported`] if the
+/// requested method is not available.
+pub enum Io {
+    /// Pick the best method available, falling back to others if the preferred one fails
+    #[default]
+    Auto,
+    /// Buffers are allocated by the driver and mapped into the process
+    Mmap,
+    /// Buffers are allocated in user space and filled by the driver
+    ///
+    /// Use [`crate::platform::Device::start_stream_into`] to capture into your own memory.
+    UserPtr,
+    /// Buffers are DMABUF file descriptors imported from another device
+    ///
+    /// Requires buffers to be supplied through [`crate::platform::Device::start_stream_into`].
+    DmaBuf,
+    /// Frames are copied from the driver with read() calls
+    ///
+    /// Slower than the streaming methods, but the only one offered by some older devices.
+    Read,
+}
+
+/// Memory supplied by the caller for a stream to capture into
+///
+/// The stream takes ownership of the buffers and releases them once it is dropped.
+pub enum Buffers {
+    /// User space memory, each buffer must be able to hold a complete frame
+    ///
+    /// Drivers may impose alignment requirements, page aligned memory is always accepted.
+    UserPtr(Vec<Box<dyn AsMut<[u8]> + Send>>),
+    #[cfg(unix)]
+    /// DMABUF file descriptors, e.g. exported by another device
+    DmaBuf(Vec<std::os::fd::OwnedFd>),
+}
+
+impl Buffers {
+    /// Returns the I/O method used to capture into these buffers
+    pub fn io(&self) -> Io {
+        match self {
+            Buffers::UserPtr(_) => Io::UserPtr,
+            #[cfg(unix)]
+            Buffers::DmaBuf(_) => Io::DmaBuf,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+/// Exact rational number
+///
+/// Used to describe frame intervals without rounding errors, e.g. 1001/30000 seconds for
+/// 29.97 frames per second. Fractions compare by value, so 1/30 equals 2/60.
+pub struct Fraction {
+    /// Numerator
+    pub numerator: u32,
+    /// Denominator
+    pub denominator: u32,
+}
+
+impl Fraction {
+    /// Returns a new fraction
+    ///
+    /// A zero denominator yields zero (0/1), which is also what V4L2 reports for intervals that
+    /// are not set.
+    ///
+    /// # Arguments
+    ///
+    /// * `numerator` - Numerator
+    /// * `denominator` - Denominator
+    pub fn new(numerator: u32, denominator: u32) -> Self {
+        if denominator == 0 {
+            return Fraction {
+                numerator: 0,
+                denominator: 1,
+            };
+        }
+
+        Fraction {
+            numerator,
+            denominator,
+        }
+    }
+
+    /// Returns the frame interval for a (whole) number of frames per second
+    ///
+    /// # Arguments
+    ///
+    /// * `fps` - Frames per second
+    pub fn from_fps(fps: u32) -> Self {
+        Fraction::new(1, fps)
+    }
+
+    /// Returns the fraction in its lowest terms
+    pub fn reduce(&self) -> Self {
+        let (mut a, mut b) = (self.numerator, self.denominator);
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+
+        // a is the greatest common divisor now, which is zero if both terms are zero
+        if a > 1 {
+            Fraction::new(self.numerator / a, self.denominator / a)
+        } else {
+            *self
+        }
+    }
+
+    /// Returns the value as floating point number
+    pub fn as_f64(&self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+
+    /// Returns the value as duration, interpreting it as seconds
+    ///
+    /// The duration is truncated to whole nanoseconds. Fractions built through their public
+    /// fields may still carry a zero denominator, which yields the maximum duration.
+    pub fn as_duration(&self) -> time::Duration {
+        if self.denominator == 0 {
+            return time::Duration::MAX;
+        }
+
+        let nanos = self.numerator as u128 * 1_000_000_000 / self.denominator as u128;
+        time::Duration::from_nanos(nanos as u64)
+    }
+}
+
+impl PartialEq for Fraction {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Fraction {}
+
+impl PartialOrd for Fraction {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Fraction {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let lhs = self.numerator as u64 * other.denominator as u64;
+        let rhs = other.numerator as u64 * self.denominator as u64;
+        lhs.cmp(&rhs)
+    }
+}
+
+impl fmt::Display for Fraction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+impl From<Fraction> for time::Duration {
+    fn from(fraction: Fraction) -> Self {
+        fraction.as_duration()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Range of values
+///
+/// Discrete values are represented by a range where `min` equals `max`. A zero `step` denotes
+/// a continuous range, i.e. any value between `min` and `max` is valid.
+pub struct Stepwise<T> {
+    /// Minimum value
+    pub min: T,
+    /// Maximum value
+    pub max: T,
+    /// Step size
+    pub step: T,
+}
+
+impl Stepwise<u32> {
+    /// Returns a range holding a single value
+    pub fn discrete(value: u32) -> Self {
+        Stepwise {
+            min: value,
+            max: value,
+            step: 0,
+        }
+    }
+
+    /// Returns true if the value is part of the range
+    // u32::is_multiple_of is too recent for the toolchains we support
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn contains(&self, value: u32) -> bool {
+        value >= self.min
+            && value <= self.max
+            && (self.step == 0 || (value - self.min) % self.step == 0)
+    }
+}
+
+impl Stepwise<Fraction> {
+    /// Returns a range holding a single value
+    pub fn discrete(value: Fraction) -> Self {
+        Stepwise {
+            min: value,
+            max: value,
+            step: Fraction::new(0, 1),
+        }
+    }
+
+    /// Returns true if the value is within the bounds of the range
+    ///
+    /// The step size is not considered since intervals are usually rounded by the backend anyway.
+    pub fn contains(&self, value: Fraction) -> bool {
+        value >= self.min && value <= self.max
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Image stream range description
+///
+/// Some devices (e.g. capture cards) do not offer a fixed set of frame sizes or intervals, but
+/// accept any value within a range instead. Any stream descriptor within the range may be used to
+/// start a stream.
+pub struct RangeDescriptor {
+    /// Width in pixels
+    pub width: Stepwise<u32>,
+    /// Height in pixels
+    pub height: Stepwise<u32>,
+    /// PixelFormat
+    pub pixfmt: PixelFormat,
+    /// Frame interval in seconds
+    pub interval: Stepwise<Fraction>,
+}
+
+impl RangeDescriptor {
+    /// Returns true if the stream descriptor is part of the range
+    ///
+    /// # Arguments
+    ///
+    /// * `desc` - Stream descriptor
+    pub fn contains(&self, desc: &Descriptor) -> bool {
+        self.pixfmt == desc.pixfmt
+            && self.width.contains(desc.width)
+            && self.height.contains(desc.height)
+            && self.interval.contains(desc.interval)
+    }
+
+    /// Returns stream descriptors for the bounds of the range
+    ///
+    /// That is, the smallest and largest frame size combined with the shortest and longest frame
+    /// interval. Discrete ranges yield exactly one descriptor.
+    pub fn bounds(&self) -> Vec<Descriptor> {
+        let mut sizes = vec![(self.width.min, self.height.min)];
+        if (self.width.max, self.height.max) != sizes[0] {
+            sizes.push((self.width.max, self.height.max));
+        }
+        let mut intervals = vec![self.interval.min];
+        if self.interval.max != intervals[0] {
+            intervals.push(self.interval.max);
+        }
+
+        let mut streams = Vec::new();
+        for (width, height) in sizes {
+            for interval in &intervals {
+                streams.push(Descriptor {
+                    width,
+                    height,
+                    pixfmt: self.pixfmt.clone(),
+                    interval: *interval,
+                });
+            }
+        }
+        streams
+    }
+}
+
+impl From<Descriptor> for RangeDescriptor {
+    fn from(desc: Descriptor) -> Self {
+        RangeDescriptor {
+            width: Stepwise::<u32>::discrete(desc.width),
+            height: Stepwise::<u32>::discrete(desc.height),
+            pixfmt: desc.pixfmt,
+            interval: Stepwise::<Fraction>::discrete(desc.interval),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+/// Frame metadata
+pub struct Metadata {
+    /// Capture timestamp
+    ///
+    /// The reference clock is backend specific. V4L2 usually reports CLOCK_MONOTONIC timestamps
+    /// while other backends measure the time since the stream was started.
+    pub timestamp: time::Duration,
+    /// Sequence number, incremented by the backend for each captured frame
+    ///
+    /// Gaps in the sequence indicate dropped frames.
+    pub sequence: u32,
+    /// Number of bytes occupied by the frame data
+    pub bytesused: usize,
+    /// Whether the driver flagged the frame data as (possibly) corrupted
+    pub corrupted: bool,
+    /// Index of the stream buffer holding the frame, if the backend uses a fixed set of buffers
+    ///
+    /// Matches the order of [`crate::platform::Stream::export`].
+    pub buffer: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// Image plane within the frame data
+///
+/// Multi-planar formats store their color components in separate planes, e.g. luma and chroma
+/// for NV12M. Each plane comes with its own row length.
+pub struct Plane {
+    /// Offset of the first byte in the frame data
+    pub offset: usize,
+    /// Number of bytes occupied by the plane
+    pub len: usize,
+    /// Length of a pixel row in bytes
+    pub stride: usize,
+}
+
+#[derive(Debug)]
+/// Frame captured by a stream
+///
+/// Dereferences to the frame data.
+pub struct Frame<'a> {
+    /// Frame data
+    pub data: &'a [u8],
+    /// Frame metadata
+    pub meta: Metadata,
+    /// Planes of multi-planar frames, empty if the data forms a single plane
+    pub planes: Vec<Plane>,
+}
+
+impl<'a> Frame<'a> {
+    /// Returns a frame with default metadata
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Frame data
+    pub fn new(data: &'a [u8]) -> Self {
+        Frame {
+            data,
+            meta: Metadata {
+                bytesused: data.len(),
+                ..Default::default()
+            },
+            planes: Vec::new(),
+        }
+    }
+
+    /// Builder pattern constructor
+    ///
+    /// # Arguments
+    ///
+    /// * `meta` - Frame metadata
+    pub fn meta(mut self, meta: Metadata) -> Self {
+        self.meta = meta;
+        self
+    }
+
+    /// Builder pattern constructor
+    ///
+    /// # Arguments
+    ///
+    /// * `planes` - Plane layout
+    pub fn planes(mut self, planes: Vec<Plane>) -> Self {
+        self.planes = planes;
+        self
+    }
+
+    /// Returns the data of a plane
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Plane index
+    pub fn plane(&self, index: usize) -> Option<&[u8]> {
+        plane(self.data, &self.planes, index)
+    }
+}
+
+fn plane<'a>(data: &'a [u8], planes: &[Plane], index: usize) -> Option<&'a [u8]> {
+    let plane = planes.get(index)?;
+    data.get(plane.offset..plane.offset + plane.len)
+}
+
+impl<'a> Deref for Frame<'a> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        self.data
+    }
+}
+
+/// Frame which owns its buffer
+///
+/// The buffer is leased from a fixed pool of stream buffers and returned to the pool once the
+/// frame is dropped. Unlike [`Frame`], owned frames stay valid when the stream advances and can
+/// be sent to other threads.
+///
+/// Dereferences to the frame data.
+pub struct OwnedFrame {
+    buf: Box<dyn AsRef<[u8]> + Send + Sync>,
+    /// Frame metadata
+    pub meta: Metadata,
+    /// Planes of multi-planar frames, empty if the data forms a single plane
+    pub planes: Vec<Plane>,
+}
+
+impl OwnedFrame {
+    /// Returns a frame with default metadata
+    ///
+    /// # Arguments
+    ///
+    /// * `buf` - Frame buffer, holding exactly the frame data
+    pub fn new<B>(buf: B) -> Self
+    where
+        B: AsRef<[u8]> + Send + Sync + 'static,
+    {
+        let bytesused = buf.as_ref().len();
+        OwnedFrame {
+            buf: Box::new(buf),
+            meta: Metadata {
+                bytesused,
+                ..Default::default()
+            },
+            planes: Vec::new(),
+        }
+    }
+
+    /// Builder pattern constructor
+    ///
+    /// # Arguments
+    ///
+    /// * `meta` - Frame metadata
+    pub fn meta(mut self, meta: Metadata) -> Self {
+        self.meta = meta;
+        self
+    }
+
+    /// Builder pattern constructor
+    ///
+    /// # Arguments
+    ///
+    /// * `planes` - Plane layout
+    pub fn planes(mut self, planes: Vec<Plane>) -> Self {
+        self.planes = planes;
+        self
+    }
+
+    /// Returns the data of a plane
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Plane index
+    pub fn plane(&self, index: usize) -> Option<&[u8]> {
+        plane(self, &self.planes, index)
+    }
+}
+
+impl Deref for OwnedFrame {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        (*self.buf).as_ref()
+    }
+}
+
+impl fmt::Debug for OwnedFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OwnedFrame")
+            .field("len", &self.len())
+            .field("meta", &self.meta)
+            .field("planes", &self.planes)
+            .finish()
+    }
+}
+
+#[derive(Clone)]
+/// Fixed size pool of heap allocated frame buffers
+///
+/// Backends which cannot lend out their native buffers copy frames into a pool instead. Buffers
+/// return to the pool when the owned frame holding them is dropped.
+pub struct Pool {
+    free: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl Pool {
+    /// Returns a pool holding a fixed number of buffers
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - Number of buffers
+    pub fn new(count: usize) -> Self {
+        Pool {
+            free: Arc::new(Mutex::new(vec![Vec::new(); count])),
+        }
+    }
+
+    /// Copies a frame into a pooled buffer
+    ///
+    /// Fails if all buffers are currently in use.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to copy
+    pub fn lease(&self, frame: &Frame) -> Result<OwnedFrame> {
+        let mut buf = match self.free.lock().unwrap().pop() {
+            Some(buf) => buf,
+            None => return Err(Error::new(ErrorKind::Other, "buffer pool exhausted")),
+        };
+
+        buf.clear();
+        buf.extend_from_slice(frame.data);
+        let buf = PoolBuffer {
+            buf,
+            free: self.free.clone(),
+        };
+        Ok(OwnedFrame::new(buf)
+            .meta(frame.meta)
+            .planes(frame.planes.clone()))
+    }
+}
+
+struct PoolBuffer {
+    buf: Vec<u8>,
+    free: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl AsRef<[u8]> for PoolBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl Drop for PoolBuffer {
+    fn drop(&mut self) {
+        if let Ok(mut free) = self.free.lock() {
+            free.push(mem::take(&mut self.buf));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fraction_zero_denominator() {
+        let fraction = Fraction::new(1, 0);
+        assert_eq!(fraction.numerator, 0);
+        assert_eq!(fraction.denominator, 1);
+        assert_eq!(Fraction::from_fps(0), Fraction::new(0, 1));
+
+        let fraction = Fraction {
+            numerator: 1,
+            denominator: 0,
+        };
+        assert_eq!(fraction.as_duration(), time::Duration::MAX);
+    }
+
+    #[test]
+    fn fraction_reduce() {
+        let fraction = Fraction::new(2, 60).reduce();
+        assert_eq!((fraction.numerator, fraction.denominator), (1, 30));
+
+        let fraction = Fraction::new(1001, 30000).reduce();
+        assert_eq!((fraction.numerator, fraction.denominator), (1001, 30000));
+
+        let fraction = Fraction::new(0, 30).reduce();
+        assert_eq!((fraction.numerator, fraction.denominator), (0, 1));
+    }
+
+    #[test]
+    fn fraction_cmp() {
+        assert_eq!(Fraction::new(1, 30), Fraction::new(2, 60));
+        assert!(Fraction::new(1, 60) < Fraction::new(1, 30));
+        assert!(Fraction::new(1001, 30000) > Fraction::new(1, 30));
+        // the terms are multiplied in 64 bits, so large values do not overflow
+        assert!(Fraction::new(u32::MAX, 1) > Fraction::new(u32::MAX - 1, 1));
+    }
+
+    #[test]
+    fn stepwise_contains() {
+        let range = Stepwise {
+            min: 16,
+            max: 64,
+            step: 8,
+        };
+        assert!(range.contains(16));
+        assert!(range.contains(40));
+        assert!(range.contains(64));
+        assert!(!range.contains(8));
+        assert!(!range.contains(42));
+        assert!(!range.contains(72));
+
+        let continuous = Stepwise {
+            min: 1,
+            max: 100,
+            step: 0,
+        };
+        assert!(continuous.contains(1));
+        assert!(continuous.contains(57));
+        assert!(!continuous.contains(0));
+
+        assert!(Stepwise::<u32>::discrete(640).contains(640));
+        assert!(!Stepwise::<u32>::discrete(640).contains(641));
+
+        let intervals = Stepwise {
+            min: Fraction::new(1, 60),
+            max: Fraction::new(1, 15),
+            step: Fraction::new(0, 1),
+        };
+        assert!(intervals.contains(Fraction::new(1, 30)));
+        assert!(!intervals.contains(Fraction::new(1, 120)));
+    }
+
+    #[test]
+    fn range_bounds() {
+        let range = RangeDescriptor {
+            width: Stepwise {
+                min: 320,
+                max: 640,
+                step: 16,
+            },
+            height: Stepwise {
+                min: 240,
+                max: 480,
+                step: 16,
+            },
+            pixfmt: PixelFormat::Rgb(24),
+            interval: Stepwise {
+                min: Fraction::new(1, 30),
+                max: Fraction::new(1, 15),
+                step: Fraction::new(0, 1),
+            },
+        };
+
+        let bounds = range.bounds();
+        let bounds: Vec<(u32, u32, Fraction)> = bounds
+            .iter()
+            .map(|desc| (desc.width, desc.height, desc.interval))
+            .collect();
+        assert_eq!(
+            bounds,
+            vec![
+                (320, 240, Fraction::new(1, 30)),
+                (320, 240, Fraction::new(1, 15)),
+                (640, 480, Fraction::new(1, 30)),
+                (640, 480, Fraction::new(1, 15)),
+            ]
+        );
+
+        let desc = Descriptor {
+            width: 640,
+            height: 480,
+            pixfmt: PixelFormat::Rgb(24),
+            interval: Fraction::new(1, 30),
+        };
+        assert_eq!(RangeDescriptor::from(desc).bounds().len(), 1);
+    }
 }
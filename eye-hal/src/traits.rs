@@ -1,6 +1,6 @@
 use crate::control;
 use crate::device;
-use crate::error::Result;
+use crate::error::{Error, ErrorKind, Result};
 use crate::stream;
 
 /// Platform context abstraction
@@ -23,17 +23,168 @@ pub trait Device<'a> {
     /// Returns the supported streams
     fn streams(&self) -> Result<Vec<stream::Descriptor>>;
 
+    /// Returns the supported stream ranges
+    ///
+    /// Unlike [`Device::streams`], this covers stepwise and continuous frame sizes and intervals.
+    /// Any stream descriptor contained in one of the ranges may be passed to
+    /// [`Device::start_stream`].
+    ///
+    /// By default, each of the discrete streams is returned as a range holding a single value.
+    fn stream_ranges(&self) -> Result<Vec<stream::RangeDescriptor>> {
+        Ok(self
+            .streams()?
+            .into_iter()
+            .map(stream::RangeDescriptor::from)
+            .collect())
+    }
+
     /// Returns a stream which produces images
     fn start_stream(&self, desc: &stream::Descriptor) -> Result<Self::Stream>;
 
     /// Returns the supported controls
     fn controls(&self) -> Result<Vec<control::Descriptor>>;
 
+    /// Returns the descriptor of a single control
+    ///
+    /// Fails with [`ErrorKind::NotFound`] if the device has no such control. By default, all
+    /// controls are queried and searched for the ID.
+    fn control_descriptor(&self, id: u32) -> Result<control::Descriptor> {
+        match self.controls()?.into_iter().find(|ctrl| ctrl.id == id) {
+            Some(ctrl) => Ok(ctrl),
+            None => Err(Error::new(ErrorKind::NotFound, "unknown control ID")),
+        }
+    }
+
     /// Returns the current control value for an ID
     fn control(&self, id: u32) -> Result<control::State>;
 
+    /// Returns the descriptor of a well-known control, if the device has it
+    ///
+    /// If several controls share the kind, backends return the one they consider most specific,
+    /// e.g. an absolute control over a generic one. By default, the first match is returned.
+    fn find_control(&self, kind: control::Kind) -> Result<Option<control::Descriptor>> {
+        Ok(self
+            .controls()?
+            .into_iter()
+            .find(|ctrl| ctrl.kind == Some(kind)))
+    }
+
+    /// Returns the current value of a well-known control
+    fn control_by_kind(&self, kind: control::Kind) -> Result<control::State> {
+        match self.find_control(kind)? {
+            Some(ctrl) => self.control(ctrl.id),
+            None => Err(Error::new(ErrorKind::NotSupported, "control not available")),
+        }
+    }
+
     /// Sets the control value, returns error for incompatible value types
     fn set_control(&mut self, id: u32, val: &control::State) -> Result<()>;
+
+    /// Returns the current value of a numerical control in a physical unit
+    ///
+    /// Fails with [`ErrorKind::NotSupported`] if the control does not report the unit.
+    fn control_in_unit(&self, id: u32, unit: control::Unit) -> Result<f64> {
+        let scale = find_scale(self.control_descriptor(id)?, unit)?;
+        match self.control(id)? {
+            control::State::Number(val) => Ok(scale.to_unit(val)),
+            control::State::Integer(val) => Ok(scale.to_unit(val as f64)),
+            _ => Err(Error::new(ErrorKind::Other, "control is not numerical")),
+        }
+    }
+
+    /// Sets the value of a numerical control in a physical unit
+    ///
+    /// The value is rounded to the closest control value. Fails with
+    /// [`ErrorKind::NotSupported`] if the control does not report the unit.
+    fn set_control_in_unit(&mut self, id: u32, val: f64, unit: control::Unit) -> Result<()> {
+        let scale = find_scale(self.control_descriptor(id)?, unit)?;
+        self.set_control(id, &control::State::Number(scale.from_unit(val).round()))
+    }
+
+    /// Sets the control value after validating it against the control descriptor
+    ///
+    /// Fails with [`ErrorKind::InvalidArgument`] without touching the device if the value does
+    /// not fit the control, see [`control::Descriptor::validate`].
+    fn set_control_checked(&mut self, id: u32, val: &control::State) -> Result<()> {
+        self.control_descriptor(id)?.validate(val)?;
+        self.set_control(id, val)
+    }
+
+    /// Sets the control value after clamping it into range and snapping it to the closest step
+    ///
+    /// Returns the value which was actually applied, see [`control::Descriptor::clamp`].
+    fn set_control_clamped(&mut self, id: u32, val: &control::State) -> Result<control::State> {
+        let val = self.control_descriptor(id)?.clamp(val)?;
+        self.set_control(id, &val)?;
+        Ok(val)
+    }
+
+    /// Sets multiple control values at once
+    ///
+    /// Backends with support for atomic updates apply either all or none of the values. By
+    /// default, the controls are set one by one and the previous values are restored on failure
+    /// as far as possible.
+    fn set_controls(&mut self, ctrls: &[(u32, control::State)]) -> Result<()> {
+        let mut prev = Vec::new();
+        for (id, val) in ctrls {
+            // write-only controls cannot be restored
+            let old = self.control(*id).ok();
+            if let Err(e) = self.set_control(*id, val) {
+                for (id, old) in prev.into_iter().rev() {
+                    if let Some(old) = old {
+                        // ignore the result
+                        let _ = self.set_control(id, &old);
+                    }
+                }
+                return Err(e);
+            }
+            prev.push((*id, old));
+        }
+
+        Ok(())
+    }
+
+    /// Sets the value of a well-known control
+    fn set_control_by_kind(&mut self, kind: control::Kind, val: &control::State) -> Result<()> {
+        match self.find_control(kind)? {
+            Some(ctrl) => self.set_control(ctrl.id, val),
+            None => Err(Error::new(ErrorKind::NotSupported, "control not available")),
+        }
+    }
+
+    /// Resets all writable and active controls to their default values
+    ///
+    /// Controls which fail to be reset are skipped, the first error is returned after all of them
+    /// have been processed.
+    ///
+    /// By default, the default value of each control descriptor is written back.
+    fn reset_controls(&mut self) -> Result<()> {
+        let mut res = Ok(());
+        for ctrl in self.controls()? {
+            let default = match &ctrl.default {
+                Some(default) if ctrl.writable() && !ctrl.inactive() => default,
+                _ => continue,
+            };
+
+            if let Err(e) = self.set_control(ctrl.id, default) {
+                if res.is_ok() {
+                    res = Err(e);
+                }
+            }
+        }
+
+        res
+    }
+}
+
+fn find_scale(ctrl: control::Descriptor, unit: control::Unit) -> Result<control::Scale> {
+    match ctrl.scale {
+        Some(scale) if scale.unit == unit => Ok(scale),
+        _ => Err(Error::new(
+            ErrorKind::NotSupported,
+            format!("control does not report values in {:?}", unit),
+        )),
+    }
 }
 
 /// Stream abstraction
@@ -29,11 +29,11 @@ impl fmt::Debug for Repr {
 
 #[derive(Debug)]
 struct Custom {
-    _kind: ErrorKind,
+    kind: ErrorKind,
     error: Box<dyn error::Error + Send + Sync>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ErrorKind {
     /// Invalid buffer.
     InvalidBuffer,
@@ -63,11 +63,19 @@ impl Error {
     {
         Error {
             repr: Repr::Custom(Box::new(Custom {
-                _kind: kind,
+                kind,
                 error: error.into(),
             })),
         }
     }
+
+    /// Returns the corresponding [`ErrorKind`] for this error.
+    pub fn kind(&self) -> ErrorKind {
+        match &self.repr {
+            Repr::Simple(kind) => *kind,
+            Repr::Custom(c) => c.kind,
+        }
+    }
 }
 
 impl From<ErrorKind> for Error {
@@ -82,7 +90,7 @@ impl From<io::Error> for Error {
     fn from(error: io::Error) -> Self {
         Error {
             repr: Repr::Custom(Box::new(Custom {
-                _kind: ErrorKind::Other,
+                kind: ErrorKind::Other,
                 error: error.into(),
             })),
         }
@@ -99,7 +107,22 @@ impl fmt::Display for Error {
 }
 
 impl error::Error for Error {
-    fn cause(&self) -> Option<&dyn std::error::Error> {
-        None
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match &self.repr {
+            Repr::Simple(_) => None,
+            Repr::Custom(ref c) => c.error.source(),
+        }
+    }
+}
+
+impl From<Error> for eye_hal::error::Error {
+    fn from(error: Error) -> Self {
+        let kind = match error.kind() {
+            ErrorKind::InvalidParam => eye_hal::error::ErrorKind::InvalidArgument,
+            ErrorKind::UnsupportedFormat => eye_hal::error::ErrorKind::NotSupported,
+            ErrorKind::InvalidBuffer | ErrorKind::Other => eye_hal::error::ErrorKind::Other,
+        };
+
+        eye_hal::error::Error::new(kind, error)
     }
 }
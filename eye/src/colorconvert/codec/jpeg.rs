@@ -17,14 +17,8 @@ impl Blueprint for Builder {
         inparams: Parameters,
         outparams: Parameters,
     ) -> Result<Box<dyn Codec + Send>> {
-        if !self
-            .src_fmts()
-            .iter()
-            .any(|pixfmt| *pixfmt == inparams.pixfmt)
-            || !self
-                .dst_fmts()
-                .iter()
-                .any(|pixfmt| *pixfmt == outparams.pixfmt)
+        if !self.src_fmts().contains(&inparams.pixfmt)
+            || !self.dst_fmts().contains(&outparams.pixfmt)
         {
             return Err(Error::from(ErrorKind::UnsupportedFormat));
         }
@@ -66,7 +60,7 @@ pub fn convert_to_rgb(src: &[u8], dst: &mut Vec<u8>) -> Result<()> {
     let mut decoder = Decoder::new(src);
     let data = match decoder.decode() {
         Ok(data) => data,
-        Err(_) => return Err(Error::new(ErrorKind::Other, "failed to decode JPEG")),
+        Err(e) => return Err(Error::new(ErrorKind::Other, e)),
     };
 
     let info = match decoder.info() {
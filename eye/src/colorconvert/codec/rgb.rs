@@ -20,14 +20,8 @@ impl Blueprint for Builder {
         inparams: Parameters,
         outparams: Parameters,
     ) -> Result<Box<dyn Codec + Send>> {
-        if !self
-            .src_fmts()
-            .iter()
-            .any(|pixfmt| *pixfmt == inparams.pixfmt)
-            || !self
-                .dst_fmts()
-                .iter()
-                .any(|pixfmt| *pixfmt == outparams.pixfmt)
+        if !self.src_fmts().contains(&inparams.pixfmt)
+            || !self.dst_fmts().contains(&outparams.pixfmt)
         {
             return Err(Error::from(ErrorKind::UnsupportedFormat));
         }
@@ -65,6 +59,7 @@ impl Codec for Instance {
                     height: self.inparams.height,
                     pixfmt: self.inparams.pixfmt.clone(),
                     stride: None,
+                    size: None,
                 };
                 convert_to_bgr(inbuf, &fmt, outbuf)
             }
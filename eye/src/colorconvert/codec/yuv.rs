@@ -21,14 +21,8 @@ impl Blueprint for Builder {
         inparams: Parameters,
         outparams: Parameters,
     ) -> Result<Box<dyn Codec + Send>> {
-        if !self
-            .src_fmts()
-            .iter()
-            .any(|pixfmt| *pixfmt == inparams.pixfmt)
-            || !self
-                .dst_fmts()
-                .iter()
-                .any(|pixfmt| *pixfmt == outparams.pixfmt)
+        if !self.src_fmts().contains(&inparams.pixfmt)
+            || !self.dst_fmts().contains(&outparams.pixfmt)
         {
             return Err(Error::from(ErrorKind::UnsupportedFormat));
         }
@@ -69,12 +63,13 @@ impl Codec for Instance {
                     height: self.inparams.height,
                     pixfmt: self.inparams.pixfmt.clone(),
                     stride: None,
+                    size: None,
                 };
 
                 match ident.as_str() {
                     "YUYV" => yuv422_to_rgb(inbuf, &fmt, outbuf),
                     "IYU2" => yuv444_to_rgb(inbuf, &fmt, outbuf),
-                    _ => return Err(Error::from(ErrorKind::UnsupportedFormat)),
+                    _ => Err(Error::from(ErrorKind::UnsupportedFormat)),
                 }
             }
             _ => Err(Error::from(ErrorKind::UnsupportedFormat)),
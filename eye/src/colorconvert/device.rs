@@ -70,6 +70,32 @@ impl<'a> DeviceTrait<'a> for Device<'a> {
         Ok(streams)
     }
 
+    fn stream_ranges(&self) -> Result<Vec<stream::RangeDescriptor>> {
+        // get all the native stream ranges
+        let mut ranges = self.inner.stream_ranges()?;
+
+        // now check which formats we can emulate
+        for blueprint in codec::blueprints() {
+            for chain in blueprint.src_fmts().iter().zip(blueprint.dst_fmts().iter()) {
+                if ranges.iter().any(|range| range.pixfmt == *chain.0)
+                    && !ranges.iter().any(|range| range.pixfmt == *chain.1)
+                {
+                    let emulated: Vec<stream::RangeDescriptor> = ranges
+                        .iter()
+                        .filter(|range| range.pixfmt == *chain.0)
+                        .map(|range| stream::RangeDescriptor {
+                            pixfmt: chain.1.clone(),
+                            ..range.clone()
+                        })
+                        .collect();
+                    ranges.extend(emulated);
+                }
+            }
+        }
+
+        Ok(ranges)
+    }
+
     fn start_stream(&self, desc: &stream::Descriptor) -> Result<Self::Stream> {
         let native_streams = self.inner.streams()?;
         if native_streams
@@ -83,7 +109,7 @@ impl<'a> DeviceTrait<'a> for Device<'a> {
         // find a supported format mapping
         let blueprints: Vec<Box<dyn codec::Blueprint>> = codec::blueprints()
             .into_iter()
-            .filter(|bp| bp.dst_fmts().iter().any(|pixfmt| *pixfmt == desc.pixfmt))
+            .filter(|bp| bp.dst_fmts().contains(&desc.pixfmt))
             .collect();
         let src_fmt = if let Some(pixfmt) = blueprints.iter().find_map(|bp| {
             for pixfmt in bp.src_fmts() {
@@ -127,15 +153,7 @@ impl<'a> DeviceTrait<'a> for Device<'a> {
             width: desc.width,
             height: desc.height,
         };
-        let codec = match blueprint.instantiate(inparams, outparams) {
-            Ok(instance) => instance,
-            Err(_e) => {
-                return Err(Error::new(
-                    ErrorKind::Other,
-                    "failed to create codec instance",
-                ))
-            }
-        };
+        let codec = blueprint.instantiate(inparams, outparams)?;
 
         // start the native stream with the base pixfmt
         let mut source_fmt = desc.clone();
@@ -143,17 +161,25 @@ impl<'a> DeviceTrait<'a> for Device<'a> {
         let native_stream = self.inner.start_stream(&source_fmt)?;
 
         // create the instance that converts the frames for us
-        return Ok(PlatformStream::Custom(Box::new(CodecStream {
+        Ok(PlatformStream::Custom(Box::new(CodecStream {
             inner: native_stream,
             codec,
             buf: Vec::new(),
-        })));
+        })))
     }
 
     fn controls(&self) -> Result<Vec<control::Descriptor>> {
         self.inner.controls()
     }
 
+    fn control_descriptor(&self, id: u32) -> Result<control::Descriptor> {
+        self.inner.control_descriptor(id)
+    }
+
+    fn find_control(&self, kind: control::Kind) -> Result<Option<control::Descriptor>> {
+        self.inner.find_control(kind)
+    }
+
     fn control(&self, id: u32) -> Result<control::State> {
         self.inner.control(id)
     }
@@ -161,4 +187,12 @@ impl<'a> DeviceTrait<'a> for Device<'a> {
     fn set_control(&mut self, id: u32, val: &control::State) -> Result<()> {
         self.inner.set_control(id, val)
     }
+
+    fn set_controls(&mut self, ctrls: &[(u32, control::State)]) -> Result<()> {
+        self.inner.set_controls(ctrls)
+    }
+
+    fn reset_controls(&mut self) -> Result<()> {
+        self.inner.reset_controls()
+    }
 }
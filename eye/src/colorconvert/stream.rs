@@ -1,4 +1,5 @@
 use eye_hal::error::Result;
+use eye_hal::stream::{Frame, Metadata};
 use eye_hal::traits::Stream;
 
 use crate::colorconvert::codec::Codec;
@@ -12,19 +13,27 @@ pub struct CodecStream<S> {
 
 impl<'a, S> Stream<'a> for CodecStream<S>
 where
-    S: Stream<'a, Item = Result<&'a [u8]>>,
+    S: Stream<'a, Item = Result<Frame<'a>>>,
 {
-    type Item = Result<&'a [u8]>;
+    type Item = Result<Frame<'a>>;
 
     fn next(&'a mut self) -> Option<Self::Item> {
         let item = self.inner.next()?;
-        let inbuf = if let Ok(buf) = item {
-            buf
+        let frame = if let Ok(frame) = item {
+            frame
         } else {
             return Some(item);
         };
 
-        self.codec.decode(inbuf, &mut self.buf).unwrap();
-        Some(Ok(&self.buf))
+        if let Err(e) = self.codec.decode(&frame, &mut self.buf) {
+            return Some(Err(e.into()));
+        }
+        // the converted frame lives in our own buffer rather than one of the stream buffers
+        let meta = Metadata {
+            bytesused: self.buf.len(),
+            buffer: None,
+            ..frame.meta
+        };
+        Some(Ok(Frame::new(&self.buf).meta(meta)))
     }
 }